@@ -1,11 +1,16 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{path::PathBuf, pin::Pin, str::FromStr};
 
 use clap::{Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
-use simple_whisper::{Event, Language, Model, WhisperBuilder};
-use strum::{EnumMessage, IntoEnumIterator};
-use tokio::fs::write;
-use tokio_stream::StreamExt;
+use simple_whisper::{
+    ConstraintsBuilder, DownloadConfig, DownloadConfigBuilder, Event, ExportFormat,
+    ExporterBuilder, Language, Model, ModelCache, ModelHeader, ModelManifest, ModelSource,
+    Preference, Segment, Sentences, SubtitleFormat, Suppress, WhisperBuilder, export_bundle,
+    import_bundle, retime,
+};
+use strum::IntoEnumIterator;
+use tokio::fs::{read_to_string, write};
+use tokio_stream::{Stream, StreamExt};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -30,8 +35,8 @@ enum Commands {
         /// Audio file
         input_file: PathBuf,
 
-        /// Which whisper model to use
-        model: Model,
+        /// Which whisper model to use: a model code, a ggml file or hf:<owner>/<repo>/<file>[@<revision>]
+        model: ModelSource,
 
         /// Audio language
         language: Language,
@@ -47,9 +52,87 @@ enum Commands {
         #[arg(long, required = false)]
         single_segment: bool,
 
+        /// Check the integrity of cached model files, downloading them again if corrupted
+        #[arg(long, required = false)]
+        verify: bool,
+
+        /// Output format: text, audacity, edl, fcpxml, premiere_xml, html, markdown, srt, vtt
+        #[arg(long, short = 'f', default_value = "text")]
+        format: ExportFormat,
+
+        /// Regroup the segments into full sentences
+        #[arg(long, required = false)]
+        sentences: bool,
+
         /// Verbose STDOUT
         #[arg(long, required = false, short = 'v')]
         verbose: bool,
+
+        /// Load the model even if this machine does not seem to have the memory to run it
+        #[arg(long, required = false)]
+        ignore_memory_check: bool,
+
+        /// Suppress the non-speech tokens: symbols, music notes and bracketed annotations
+        #[arg(long, required = false)]
+        suppress_non_speech: bool,
+
+        /// Text the model must not output, e.g. "♪". Can be repeated
+        #[arg(long, value_name = "TEXT")]
+        suppress: Vec<String>,
+    },
+    /// Compute the timing of a known transcript of the audio file
+    Align {
+        /// Audio file
+        input_file: PathBuf,
+
+        /// Text file with the exact transcript, one segment per line
+        transcript_file: PathBuf,
+
+        /// Which whisper model to use: a model code, a ggml file or hf:<owner>/<repo>/<file>[@<revision>]
+        model: ModelSource,
+
+        /// Audio language
+        language: Language,
+
+        /// Output file
+        output_file: PathBuf,
+
+        /// Ignore cached model files
+        #[arg(long, required = false)]
+        ignore_cache: bool,
+
+        /// Output format: text, audacity, edl, fcpxml, premiere_xml, html, markdown, srt, vtt
+        #[arg(long, short = 'f', default_value = "srt")]
+        format: ExportFormat,
+
+        /// Load the model even if this machine does not seem to have the memory to run it
+        #[arg(long, required = false)]
+        ignore_memory_check: bool,
+    },
+    /// Realign an existing SRT/VTT subtitle file on a fresh transcription of the audio
+    Retime {
+        /// Audio file
+        input_file: PathBuf,
+
+        /// Subtitle file with drifted timing
+        subtitle_file: PathBuf,
+
+        /// Which whisper model to use: a model code, a ggml file or hf:<owner>/<repo>/<file>[@<revision>]
+        model: ModelSource,
+
+        /// Audio language
+        language: Language,
+
+        /// Output subtitle file, the format is detected from the extension
+        output_file: PathBuf,
+
+        /// Ignore cached model files
+        #[arg(long, required = false)]
+        ignore_cache: bool,
+
+        /// Load the model even if this machine does not seem to have the memory to run it
+        #[arg(long, required = false)]
+        ignore_memory_check: bool,
     },
 }
 
@@ -69,25 +152,105 @@ enum LangCommands {
 enum ModelCommands {
     /// List supported models
     List,
-    /// Download a model by providing its code
+    /// Download a model by providing its code or registered name
     Download {
-        /// The code associated to the model
-        code: String,
+        /// The model to download: a model code, a registered name or hf:<owner>/<repo>/<file>[@<revision>]
+        model: ModelSource,
 
         /// Ignore cached model files
         #[arg(long, required = false)]
         ignore_cache: bool,
+
+        /// Check the integrity of a cached model file, downloading it again if corrupted
+        #[arg(long, required = false)]
+        verify: bool,
+    },
+    /// List the downloaded models and the space they use
+    Cached,
+    /// Delete a downloaded model by providing its code or registered name
+    Remove {
+        /// The model to delete: a model code, a registered name or hf:<owner>/<repo>/<file>[@<revision>]
+        model: ModelSource,
+    },
+    /// Delete all the downloaded models except the ones to keep
+    Prune {
+        /// Codes of the models to keep
+        #[arg(long, short = 'k', num_args = 1..)]
+        keep: Vec<Model>,
+    },
+    /// Pack downloaded models into a bundle for machines without network access
+    Export {
+        /// Output bundle file
+        bundle: PathBuf,
+
+        /// Codes of the models to pack
+        #[arg(required = true)]
+        models: Vec<Model>,
+    },
+    /// Add the models of a bundle to the cache
+    Import {
+        /// Bundle file created by the export command
+        bundle: PathBuf,
+    },
+    /// Rank the models able to run on this machine
+    Recommend {
+        /// Audio language
+        #[arg(long, short = 'l', default_value = "en")]
+        language: Language,
+
+        /// Trade-off between speed and accuracy: speed, balanced, accuracy
+        #[arg(long, short = 'p', default_value = "balanced")]
+        preference: Preference,
+
+        /// Memory available to the model in MB, detected when omitted
+        #[arg(long)]
+        memory: Option<u64>,
+
+        /// How many models to show
+        #[arg(long, short = 'n', default_value = "5")]
+        count: usize,
+    },
+    /// Show the hyperparameters stored in a ggml model file
+    Inspect {
+        /// The ggml model file
+        path: PathBuf,
     },
 }
 
+/// Download settings checking the integrity of cached files when requested
+fn verification(verify: bool) -> DownloadConfig {
+    DownloadConfigBuilder::default()
+        .verify_cache(verify)
+        .redownload_corrupted(verify)
+        .build()
+        .unwrap()
+}
+
+/// Format a size in bytes using the most readable unit
+fn human_size(bytes: u64) -> String {
+    if bytes >= 1_000_000_000 {
+        format!("{:.1} GB", bytes as f64 / 1e9)
+    } else {
+        format!("{} MB", bytes / 1_000_000)
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    // Registered before parsing, custom models are resolved by name in the arguments
+    match ModelManifest::from_env() {
+        Ok(manifest) => manifest.into_iter().for_each(ModelManifest::register),
+        Err(err) => {
+            println!("{err} occurred\nAborting!");
+            return;
+        }
+    }
     let cli = Cli::parse();
     match cli.command {
         Commands::Languages { sub_command } => match sub_command {
             LangCommands::List => {
                 for lang in Language::iter() {
-                    println!("{}", lang.get_message().unwrap())
+                    println!("{} ({}) - {lang}", lang.name(), lang.native_name())
                 }
             }
             LangCommands::Check { code } => match Language::from_str(&code) {
@@ -102,7 +265,13 @@ async fn main() {
             language,
             ignore_cache,
             single_segment,
+            verify,
+            format,
+            sentences,
             verbose,
+            ignore_memory_check,
+            suppress_non_speech,
+            suppress,
         } => {
             match WhisperBuilder::default()
                 .language(language)
@@ -110,11 +279,32 @@ async fn main() {
                 .progress_bar(true)
                 .force_download(ignore_cache)
                 .force_single_segment(single_segment)
+                .ignore_memory_check(ignore_memory_check)
+                .suppress_non_speech(suppress_non_speech)
+                .suppress(suppress.into_iter().map(Suppress::from).collect::<Vec<_>>())
+                .download_config(verification(verify))
                 .build()
             {
                 Ok(model) => {
-                    let mut segments: Vec<String> = Vec::new();
-                    let mut stream = model.transcribe(input_file);
+                    let mut segments: Vec<Segment> = Vec::new();
+                    let exporter = ExporterBuilder::default()
+                        .format(format)
+                        .title(
+                            input_file
+                                .file_name()
+                                .map(|name| name.to_string_lossy().into_owned())
+                                .unwrap_or_default(),
+                        )
+                        .audio(input_file.to_string_lossy())
+                        .language(language)
+                        .build()
+                        .unwrap();
+                    let stream = Box::pin(model.transcribe(input_file));
+                    let mut stream: Pin<Box<dyn Stream<Item = _>>> = if sentences {
+                        Box::pin(Sentences::new(stream, language))
+                    } else {
+                        stream
+                    };
                     let pb = if verbose {
                         None
                     } else {
@@ -131,7 +321,6 @@ async fn main() {
                         match msg {
                             Ok(msg) => {
                                 if msg.is_segment() {
-                                    segments.push(msg.to_string());
                                     if verbose {
                                         println!("{msg:?}")
                                     } else if let Event::Segment { percentage, .. } = msg {
@@ -139,6 +328,9 @@ async fn main() {
                                             .unwrap()
                                             .set_position((percentage * 100.) as u64);
                                     }
+                                    if let Ok(segment) = msg.try_into() {
+                                        segments.push(segment);
+                                    }
                                 }
                             }
                             Err(err) => println!("{err} occurred\nAborting!"),
@@ -147,7 +339,105 @@ async fn main() {
                     if let Some(pb) = pb {
                         pb.finish();
                     }
-                    if let Err(err) = write(output_file, segments.join("\n")).await {
+                    if let Err(err) = write(output_file, exporter.export(&segments)).await {
+                        println!("{err} occurred\nAborting!");
+                    }
+                }
+                Err(err) => println!("{err} occurred\nAborting!"),
+            }
+        }
+        Commands::Align {
+            input_file,
+            transcript_file,
+            model,
+            language,
+            output_file,
+            ignore_cache,
+            format,
+            ignore_memory_check,
+        } => {
+            let reference = match read_to_string(&transcript_file).await {
+                Ok(reference) => reference,
+                Err(err) => {
+                    println!("{err} occurred\nAborting!");
+                    return;
+                }
+            };
+            let alignment = match WhisperBuilder::default()
+                .language(language)
+                .model(model)
+                .progress_bar(true)
+                .force_download(ignore_cache)
+                .ignore_memory_check(ignore_memory_check)
+                .build()
+            {
+                Ok(model) => model.align(input_file, reference).await,
+                Err(err) => {
+                    println!("{err} occurred\nAborting!");
+                    return;
+                }
+            };
+            match alignment {
+                Ok(alignment) => {
+                    for span in &alignment.unaligned {
+                        println!(
+                            "Unaligned {:?} - {:?}: {}",
+                            span.start_offset, span.end_offset, span.text
+                        );
+                    }
+                    let exporter = ExporterBuilder::default().format(format).build().unwrap();
+                    if let Err(err) = write(output_file, exporter.export(&alignment.segments)).await
+                    {
+                        println!("{err} occurred\nAborting!");
+                    }
+                }
+                Err(err) => println!("{err} occurred\nAborting!"),
+            }
+        }
+        Commands::Retime {
+            input_file,
+            subtitle_file,
+            model,
+            language,
+            output_file,
+            ignore_cache,
+            ignore_memory_check,
+        } => {
+            let Some(format) = SubtitleFormat::from_path(&output_file) else {
+                println!("Unsupported subtitle format for {}", output_file.display());
+                return;
+            };
+            let captions = match SubtitleFormat::load(&subtitle_file) {
+                Ok(captions) => captions,
+                Err(err) => {
+                    println!("{err} occurred\nAborting!");
+                    return;
+                }
+            };
+            match WhisperBuilder::default()
+                .language(language)
+                .model(model)
+                .progress_bar(true)
+                .force_download(ignore_cache)
+                .ignore_memory_check(ignore_memory_check)
+                .build()
+            {
+                Ok(model) => {
+                    let mut transcript: Vec<Segment> = Vec::new();
+                    let mut stream = model.transcribe(input_file);
+                    while let Some(msg) = stream.next().await {
+                        match msg {
+                            Ok(msg) => {
+                                if let Ok(segment) = msg.try_into() {
+                                    transcript.push(segment);
+                                }
+                            }
+                            Err(err) => println!("{err} occurred\nAborting!"),
+                        }
+                    }
+                    let exporter = ExporterBuilder::default().format(format).build().unwrap();
+                    let retimed = retime(&captions, &transcript);
+                    if let Err(err) = write(output_file, exporter.export(&retimed)).await {
                         println!("{err} occurred\nAborting!");
                     }
                 }
@@ -156,19 +446,133 @@ async fn main() {
         }
         Commands::Models { sub_command } => match sub_command {
             ModelCommands::List => {
-                for model in Model::iter() {
-                    println!("{model}")
+                println!(
+                    "{:<20} {:<15} {:<6} {:<7} {:>9} {:>9} {:>6} {:<11} TDRZ",
+                    "CODE", "FAMILY", "QUANT", "LANG", "SIZE", "MEMORY", "SPEED", "TRANSLATION"
+                );
+                for info in Model::iter().map(|model| model.info()) {
+                    println!(
+                        "{:<20} {:<15} {:<6} {:<7} {:>9} {:>9} {:>5}x {:<11} {}",
+                        info.code,
+                        info.family.to_string(),
+                        info.quantization.to_string(),
+                        if info.english_only {
+                            "english"
+                        } else {
+                            "multi"
+                        },
+                        human_size(info.download_size),
+                        human_size(info.memory),
+                        info.relative_speed,
+                        if info.translation { "yes" } else { "no" },
+                        if info.tdrz { "yes" } else { "no" },
+                    )
                 }
             }
-            ModelCommands::Download { code, ignore_cache } => match Model::from_str(&code) {
-                Ok(model) => {
-                    if let Err(err) = model.download_model(ignore_cache).await {
-                        println!("Error {err}.\nAborting!");
-                    } else {
-                        println!("Download completed");
+            ModelCommands::Download {
+                model,
+                ignore_cache,
+                verify,
+            } => {
+                if let Err(err) = model
+                    .download_model_with_config(ignore_cache, &verification(verify))
+                    .await
+                {
+                    println!("Error {err}.\nAborting!");
+                } else {
+                    println!("Download completed");
+                }
+            }
+            ModelCommands::Cached => {
+                let cache = ModelCache::default();
+                for cached in cache.list_cached() {
+                    println!(
+                        "{:<20} {:>9} {}",
+                        cached.model.info().code,
+                        human_size(cached.size),
+                        cached.path.display()
+                    );
+                }
+                println!("Total {}", human_size(cache.total_size()));
+            }
+            ModelCommands::Remove { model } => match ModelCache::default().remove_source(&model) {
+                Ok(true) => println!("{model} removed"),
+                Ok(false) => println!("{model} is not cached"),
+                Err(err) => println!("Error {err}.\nAborting!"),
+            },
+            ModelCommands::Prune { keep } => match ModelCache::default().prune(&keep) {
+                Ok(removed) => {
+                    for model in removed {
+                        println!("{} removed", model.info().code);
                     }
                 }
-                Err(_) => println!("{code} not associated to any supported model"),
+                Err(err) => println!("Error {err}.\nAborting!"),
+            },
+            ModelCommands::Export { bundle, models } => match export_bundle(&models, &bundle) {
+                Ok(manifest) => println!(
+                    "{} models exported to {}",
+                    manifest.models.len(),
+                    bundle.display()
+                ),
+                Err(err) => println!("Error {err}.\nAborting!"),
+            },
+            ModelCommands::Import { bundle } => match import_bundle(&bundle) {
+                Ok(models) => {
+                    for model in models {
+                        println!("{} imported", model.info().code);
+                    }
+                }
+                Err(err) => println!("Error {err}.\nAborting!"),
+            },
+            ModelCommands::Recommend {
+                language,
+                preference,
+                memory,
+                count,
+            } => {
+                let mut constraints = ConstraintsBuilder::default();
+                constraints.language(language).preference(preference);
+                if let Some(memory) = memory {
+                    constraints.available_memory(memory * 1_000_000);
+                }
+                let recommendations = Model::recommend(&constraints.build().unwrap());
+                if recommendations.is_empty() {
+                    println!("No model fits the available memory");
+                }
+                for (rank, recommendation) in recommendations.iter().take(count).enumerate() {
+                    println!(
+                        "{}. {} (score {:.2})",
+                        rank + 1,
+                        recommendation.model.info().code,
+                        recommendation.score
+                    );
+                    for reason in &recommendation.reasons {
+                        println!("   - {reason}");
+                    }
+                }
+            }
+            ModelCommands::Inspect { path } => match ModelHeader::read(&path) {
+                Ok(header) => {
+                    let family = header
+                        .family()
+                        .map(|family| family.to_string())
+                        .unwrap_or("unknown".to_owned());
+                    println!("family         {family}");
+                    println!("weight type    {}", header.weight_type());
+                    println!("multilingual   {}", header.is_multilingual());
+                    println!("n_vocab        {}", header.n_vocab);
+                    println!("n_audio_ctx    {}", header.n_audio_ctx);
+                    println!("n_audio_state  {}", header.n_audio_state);
+                    println!("n_audio_head   {}", header.n_audio_head);
+                    println!("n_audio_layer  {}", header.n_audio_layer);
+                    println!("n_text_ctx     {}", header.n_text_ctx);
+                    println!("n_text_state   {}", header.n_text_state);
+                    println!("n_text_head    {}", header.n_text_head);
+                    println!("n_text_layer   {}", header.n_text_layer);
+                    println!("n_mels         {}", header.n_mels);
+                    println!("ftype          {}", header.ftype);
+                }
+                Err(err) => println!("Error {err}.\nAborting!"),
             },
         },
     }
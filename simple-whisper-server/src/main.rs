@@ -1,4 +1,4 @@
-use std::{str::FromStr, time::Duration};
+use std::{path::PathBuf, str::FromStr};
 
 use axum::{
     Json, Router,
@@ -8,16 +8,25 @@ use axum::{
     },
     http::{Request, StatusCode},
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{delete, get, post},
     serve,
 };
 use clap::Parser;
 use serde::{Deserialize, Serialize};
-use simple_whisper::{Event, Language, Model, Whisper, WhisperBuilder};
-use strum::{EnumIs, EnumMessage, IntoEnumIterator};
+use simple_whisper::{
+    Backpressure, Event, Language, Model, ModelCache, ModelManifest, ModelSource, Whisper,
+    WhisperBuilder,
+};
+use strum::{EnumIs, IntoEnumIterator};
 use tempfile::NamedTempFile;
 use thiserror::Error;
-use tokio::{fs::write, net::TcpListener, spawn, sync::mpsc::unbounded_channel};
+use tokio::{
+    fs::write,
+    net::TcpListener,
+    spawn,
+    sync::mpsc::unbounded_channel,
+    task::{JoinError, spawn_blocking},
+};
 use tokio_stream::StreamExt;
 use tower_http::trace::TraceLayer;
 use tracing::info_span;
@@ -29,6 +38,14 @@ struct Cli {
     /// Server listening port
     #[arg(long, short = 'p', default_value = "3000")]
     server_port: u16,
+
+    /// TOML or JSON manifest of custom models, SIMPLE_WHISPER_MODELS is used when omitted
+    #[arg(long, short = 'm')]
+    manifest: Option<PathBuf>,
+
+    /// Expose the cache administration endpoints under /admin
+    #[arg(long)]
+    admin: bool,
 }
 
 #[derive(Error, Debug)]
@@ -37,6 +54,12 @@ enum Error {
     ModelNotSupported(String),
     #[error("Language {0} not supported")]
     LanguageNotSupported(String),
+    #[error("Model {0} not cached")]
+    ModelNotCached(String),
+    #[error("Cache error {0}")]
+    Cache(#[from] simple_whisper::Error),
+    #[error("Cache task failed {0}")]
+    Task(#[from] JoinError),
 }
 
 impl IntoResponse for Error {
@@ -44,20 +67,50 @@ impl IntoResponse for Error {
         match self {
             Error::ModelNotSupported(_) => (StatusCode::BAD_REQUEST, format!("{self}")),
             Error::LanguageNotSupported(_) => (StatusCode::BAD_REQUEST, format!("{self}")),
+            Error::ModelNotCached(_) => (StatusCode::NOT_FOUND, format!("{self}")),
+            Error::Cache(_) | Error::Task(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, format!("{self}"))
+            }
         }
         .into_response()
     }
 }
 #[derive(Deserialize, Serialize)]
 struct LanguageResponse {
-    id: String,
+    id: Language,
     lang: String,
 }
 
 #[derive(Deserialize, Serialize)]
 struct ModelResponse {
-    id: String,
+    id: Model,
     model: String,
+    family: String,
+    quantization: String,
+    english_only: bool,
+    download_size: u64,
+    memory: u64,
+    relative_speed: f32,
+    translation: bool,
+    tdrz: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedModelResponse {
+    id: Model,
+    path: String,
+    size: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheResponse {
+    models: Vec<CachedModelResponse>,
+    total_size: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+struct PruneParameters {
+    keep: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -71,66 +124,39 @@ struct TranscribeParameters {
     ignore_cache: bool,
     #[serde(default)]
     single_segment: bool,
+    #[serde(default)]
+    suppress_non_speech: bool,
 }
 
-#[derive(EnumIs, Debug, Deserialize, Serialize)]
+/// Messages sent on the websockets
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
 enum ServerResponse {
-    FileStarted {
-        file: String,
-    },
-    FileCompleted {
-        file: String,
-    },
-    FileProgress {
-        file: String,
-        percentage: f32,
-        elapsed_time: Duration,
-        remaining_time: Duration,
-    },
-    Failed,
-    DownloadModelCompleted,
-    Segment {
-        start_offset: Duration,
-        end_offset: Duration,
-        percentage: f32,
-        transcription: String,
-    },
+    /// Progress of the download or of the transcription
+    Event(Event),
+    /// Outcome of the request
+    Status(Status),
 }
 
-impl From<Event> for ServerResponse {
-    fn from(value: Event) -> Self {
-        match value {
-            Event::DownloadStarted { file } => Self::FileStarted { file },
-            Event::DownloadCompleted { file } => Self::FileCompleted { file },
-            Event::Segment {
-                start_offset,
-                end_offset,
-                percentage,
-                transcription,
-            } => Self::Segment {
-                start_offset,
-                end_offset,
-                percentage,
-                transcription,
-            },
-            Event::DownloadProgress {
-                file,
-                percentage,
-                elapsed_time,
-                remaining_time,
-            } => Self::FileProgress {
-                file,
-                percentage,
-                elapsed_time,
-                remaining_time,
-            },
-        }
-    }
+/// Tagged like the [Event]s, e.g. `{"event":"failed","error":"..."}`
+#[derive(EnumIs, Debug, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Status {
+    Failed { error: String },
+    DownloadModelCompleted,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    let manifest = match cli.manifest {
+        Some(path) => ModelManifest::load(&path).map(Some),
+        None => ModelManifest::from_env(),
+    };
+    match manifest {
+        Ok(manifest) => manifest.into_iter().for_each(ModelManifest::register),
+        Err(err) => panic!("{err}"),
+    }
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
@@ -143,11 +169,11 @@ async fn main() {
     let listener = TcpListener::bind(("127.0.0.1", cli.server_port))
         .await
         .unwrap();
-    serve(listener, app()).await.unwrap();
+    serve(listener, app(cli.admin)).await.unwrap();
 }
 
-fn app() -> Router {
-    Router::new()
+fn app(admin: bool) -> Router {
+    let router = Router::new()
         .layer(
             TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
                 let matched_path = request
@@ -164,7 +190,12 @@ fn app() -> Router {
         )
         .nest("/languages", languages_router())
         .nest("/models", models_router())
-        .nest("/transcribe", transcribe_router())
+        .nest("/transcribe", transcribe_router());
+    if admin {
+        router.nest("/admin", admin_router())
+    } else {
+        router
+    }
 }
 
 fn languages_router() -> Router {
@@ -176,13 +207,9 @@ fn languages_router() -> Router {
 async fn list_languages() -> Json<Vec<LanguageResponse>> {
     Json(
         Language::iter()
-            .map(|l| {
-                let binding = l.get_message().unwrap();
-                let (lang, code) = binding.split_once('-').unwrap();
-                LanguageResponse {
-                    id: code.trim().to_owned(),
-                    lang: lang.trim().to_owned(),
-                }
+            .map(|l| LanguageResponse {
+                id: l,
+                lang: l.name().to_owned(),
             })
             .collect(),
     )
@@ -203,12 +230,19 @@ fn models_router() -> Router {
 async fn list_models() -> Json<Vec<ModelResponse>> {
     Json(
         Model::iter()
-            .map(|l| {
-                let binding = l.to_string();
-                let (model, code) = binding.split_once('-').unwrap();
+            .map(|model| {
+                let info = model.info();
                 ModelResponse {
-                    id: code.trim().to_owned(),
-                    model: model.trim().to_owned(),
+                    id: model,
+                    model: info.name,
+                    family: info.family.to_string(),
+                    quantization: info.quantization.to_string(),
+                    english_only: info.english_only,
+                    download_size: info.download_size,
+                    memory: info.memory,
+                    relative_speed: info.relative_speed,
+                    translation: info.translation,
+                    tdrz: info.tdrz,
                 }
             })
             .collect(),
@@ -220,21 +254,19 @@ async fn download_model(
     Path(id): Path<String>,
     parameters: Query<ModelParameters>,
 ) -> Response {
-    let maybe_model: Result<Model, Error> =
-        Model::from_str(&id).map_err(|_| Error::ModelNotSupported(id));
-    match maybe_model {
+    match served_model(id) {
         Ok(model) => ws.on_upgrade(|socket| handle_download_model(socket, model, parameters.0)),
         Err(err) => err.into_response(),
     }
 }
 
-async fn handle_download_model(socket: WebSocket, model: Model, params: ModelParameters) {
+async fn handle_download_model(socket: WebSocket, model: ModelSource, params: ModelParameters) {
     let _ = internal_handle_download_model(socket, model, params).await;
 }
 
 async fn internal_handle_download_model(
     mut socket: WebSocket,
-    model: Model,
+    model: ModelSource,
     params: ModelParameters,
 ) -> anyhow::Result<()> {
     let (tx, mut rx) = unbounded_channel();
@@ -244,27 +276,79 @@ async fn internal_handle_download_model(
     while let Some(msg) = rx.recv().await {
         socket
             .send(Message::Text(serde_json::to_string(
-                &Into::<ServerResponse>::into(msg),
+                &ServerResponse::Event(msg),
             )?))
             .await?;
     }
-    match download.await {
-        Ok(_) => {
-            socket
-                .send(Message::Text(serde_json::to_string(
-                    &ServerResponse::DownloadModelCompleted,
-                )?))
-                .await?
-        }
-        Err(_) => {
-            socket
-                .send(Message::Text(serde_json::to_string(
-                    &ServerResponse::Failed,
-                )?))
-                .await?
+    let status = match download.await {
+        Ok(Ok(_)) => Status::DownloadModelCompleted,
+        Ok(Err(err)) => Status::Failed {
+            error: err.to_string(),
+        },
+        Err(err) => Status::Failed {
+            error: err.to_string(),
+        },
+    };
+    socket
+        .send(Message::Text(serde_json::to_string(
+            &ServerResponse::Status(status),
+        )?))
+        .await?;
+    Ok(())
+}
+
+fn admin_router() -> Router {
+    Router::new()
+        .route("/cache", get(list_cached))
+        .route("/cache/prune", post(prune_cache))
+        .route("/cache/:id", delete(remove_cached))
+}
+
+async fn list_cached() -> Result<Json<CacheResponse>, Error> {
+    let response = spawn_blocking(|| {
+        let cache = ModelCache::default();
+        CacheResponse {
+            models: cache
+                .list_cached()
+                .into_iter()
+                .map(|cached| CachedModelResponse {
+                    id: cached.model,
+                    path: cached.path.to_string_lossy().into_owned(),
+                    size: cached.size,
+                })
+                .collect(),
+            total_size: cache.total_size(),
         }
+    })
+    .await?;
+    Ok(Json(response))
+}
+
+async fn remove_cached(Path(id): Path<String>) -> Result<(), Error> {
+    let model = Model::from_str(&id).map_err(|_| Error::ModelNotSupported(id.clone()))?;
+    if spawn_blocking(move || ModelCache::default().remove(&model)).await?? {
+        Ok(())
+    } else {
+        Err(Error::ModelNotCached(id))
+    }
+}
+
+async fn prune_cache(Json(params): Json<PruneParameters>) -> Result<Json<Vec<Model>>, Error> {
+    let keep = params
+        .keep
+        .into_iter()
+        .map(|id| Model::from_str(&id).map_err(|_| Error::ModelNotSupported(id)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let pruned = spawn_blocking(move || ModelCache::default().prune(&keep)).await??;
+    Ok(Json(pruned))
+}
+
+/// Resolve built-in and registered models, paths on the server are not exposed
+fn served_model(id: String) -> Result<ModelSource, Error> {
+    match ModelSource::from_str(&id) {
+        Ok(source @ (ModelSource::Model(_) | ModelSource::Registered(_))) => Ok(source),
+        _ => Err(Error::ModelNotSupported(id)),
     }
-    Ok(())
 }
 
 fn transcribe_router() -> Router {
@@ -278,7 +362,7 @@ async fn transcribe(
     Path((model, lang)): Path<(String, String)>,
     parameters: Query<TranscribeParameters>,
 ) -> Response {
-    let model = Model::from_str(&model).map_err(|_| Error::ModelNotSupported(model));
+    let model = served_model(model);
     let lang = Language::from_str(&lang).map_err(|_| Error::LanguageNotSupported(lang));
     if let Err(err) = model {
         return err.into_response();
@@ -293,6 +377,9 @@ async fn transcribe(
         .model(model.unwrap())
         .force_download(parameters.0.ignore_cache)
         .force_single_segment(parameters.0.single_segment)
+        .suppress_non_speech(parameters.0.suppress_non_speech)
+        // Only the segments are sent to the client
+        .backpressure(Backpressure::DropProgress)
         .build()
         .unwrap();
 
@@ -317,15 +404,17 @@ async fn internal_handle_transcription_model(
                     if msg.is_segment() {
                         socket
                             .send(Message::Text(serde_json::to_string(
-                                &Into::<ServerResponse>::into(msg),
+                                &ServerResponse::Event(msg),
                             )?))
                             .await?;
                     }
                 }
-                Err(_) => {
+                Err(err) => {
                     socket
                         .send(Message::Text(serde_json::to_string(
-                            &ServerResponse::Failed,
+                            &ServerResponse::Status(Status::Failed {
+                                error: err.to_string(),
+                            }),
                         )?))
                         .await?
                 }
@@ -345,7 +434,7 @@ mod tests {
     use reqwest_websocket::{Message, RequestBuilderExt};
     use tokio::{net::TcpListener, spawn};
 
-    use crate::{LanguageResponse, ModelResponse, ServerResponse, app};
+    use crate::{CacheResponse, LanguageResponse, ModelResponse, ServerResponse, Status, app};
 
     macro_rules! test_file {
         ($file_name:expr) => {
@@ -356,7 +445,7 @@ mod tests {
     #[tokio::test]
     async fn integration_test_languages() {
         let listener = TcpListener::bind("127.0.0.1:3000").await.unwrap();
-        spawn(serve(listener, app()).into_future());
+        spawn(serve(listener, app(false)).into_future());
 
         let languages: Vec<LanguageResponse> = reqwest::get("http://127.0.0.1:3000/languages/list")
             .await
@@ -382,7 +471,7 @@ mod tests {
     #[tokio::test]
     async fn integration_test_models() {
         let listener = TcpListener::bind("127.0.0.1:4000").await.unwrap();
-        spawn(serve(listener, app()).into_future());
+        spawn(serve(listener, app(false)).into_future());
 
         let models: Vec<ModelResponse> = reqwest::get("http://127.0.0.1:4000/models/list")
             .await
@@ -406,20 +495,72 @@ mod tests {
         while let Some(Ok(Message::Text(msg))) = rx.next().await {
             let msg: ServerResponse = serde_json::from_str(&msg).unwrap();
             println!("{msg:?}");
-            assert!(
-                msg.is_file_started()
-                    || msg.is_file_completed()
-                    || msg.is_file_progress()
-                    || msg.is_download_model_completed()
-            )
+            assert!(match msg {
+                ServerResponse::Event(event) => {
+                    event.is_download_started()
+                        || event.is_download_completed()
+                        || event.is_download_progress()
+                }
+                ServerResponse::Status(status) => status.is_download_model_completed(),
+            })
         }
     }
 
+    #[tokio::test]
+    async fn integration_test_admin() {
+        let listener = TcpListener::bind("127.0.0.1:6000").await.unwrap();
+        spawn(serve(listener, app(true)).into_future());
+
+        let cache: CacheResponse = reqwest::get("http://127.0.0.1:6000/admin/cache")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(cache.total_size <= cache.models.iter().map(|model| model.size).sum::<u64>());
+
+        // Invalid codes are rejected before touching the cache
+        let client = Client::new();
+        let bad_request = client
+            .delete("http://127.0.0.1:6000/admin/cache/tiny_xx")
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(bad_request.as_u16(), 400);
+        let bad_request = client
+            .post("http://127.0.0.1:6000/admin/cache/prune")
+            .json(&serde_json::json!({ "keep": ["tiny_xx"] }))
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(bad_request.as_u16(), 400);
+        // Nothing is pruned without an explicit list of models to keep
+        let bad_request = client
+            .post("http://127.0.0.1:6000/admin/cache/prune")
+            .json(&serde_json::json!({}))
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert!(bad_request.is_client_error());
+
+        // The endpoints are only served when enabled
+        let listener = TcpListener::bind("127.0.0.1:6001").await.unwrap();
+        spawn(serve(listener, app(false)).into_future());
+        let not_found = reqwest::get("http://127.0.0.1:6001/admin/cache")
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(not_found.as_u16(), 404);
+    }
+
     #[ignore]
     #[tokio::test]
     async fn integration_test_transcription() {
         let listener = TcpListener::bind("127.0.0.1:5000").await.unwrap();
-        spawn(serve(listener, app()).into_future());
+        spawn(serve(listener, app(false)).into_future());
 
         let client = Client::new();
         let websocket = client
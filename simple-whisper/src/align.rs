@@ -0,0 +1,519 @@
+use std::{ops::Range, time::Duration};
+
+use crate::Segment;
+
+/// Extra diagonals explored around the expected alignment path
+const BAND: usize = 128;
+
+/// Timing of a known transcript, produced by [crate::Whisper::align]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Alignment {
+    /// Every word of the reference text, in order
+    pub words: Vec<AlignedWord>,
+    /// Every non empty line of the reference text
+    pub segments: Vec<Segment>,
+    /// Runs of reference words that have not been recognized in the audio
+    pub unaligned: Vec<UnalignedSpan>,
+}
+
+/// A reference word with its position in the audio
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlignedWord {
+    pub text: String,
+    pub start_offset: Duration,
+    pub end_offset: Duration,
+    /// False when the timing has been estimated from the surrounding words
+    pub aligned: bool,
+}
+
+/// A run of reference words missing or misrecognized in the audio
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnalignedSpan {
+    /// Indexes of the words in [Alignment::words]
+    pub words: Range<usize>,
+    pub text: String,
+    /// Estimated start of the span
+    pub start_offset: Duration,
+    /// Estimated end of the span
+    pub end_offset: Duration,
+}
+
+/// A word with its position in the audio
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TimedWord {
+    pub text: String,
+    pub start_offset: Duration,
+    pub end_offset: Duration,
+}
+
+/// How a reference word has been paired with the hypothesis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum WordMatch {
+    /// Same word found at the given hypothesis index
+    Exact(usize),
+    /// A different word occupies the same position at the given hypothesis index
+    Substituted(usize),
+    /// No hypothesis word is associated to the reference word
+    Missing,
+}
+
+impl WordMatch {
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            WordMatch::Exact(idx) | WordMatch::Substituted(idx) => Some(*idx),
+            WordMatch::Missing => None,
+        }
+    }
+}
+
+/// Time the reference text using the words recognized in the audio.
+///
+/// Words matched exactly take the recognized timing, misrecognized words take the
+/// timing of the word found at their position and missing words share the gap
+/// between their neighbours.
+pub(crate) fn align_reference(reference: &str, hypothesis: &[TimedWord]) -> Alignment {
+    let hypothesis_words: Vec<String> = hypothesis.iter().map(|w| normalize(&w.text)).collect();
+
+    let mut lines = Vec::new();
+    let mut words = Vec::new();
+    let mut normalized = Vec::new();
+    for line in reference.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let first = words.len();
+        for word in split_words(line) {
+            words.push(word.to_owned());
+            normalized.push(normalize(word));
+        }
+        if words.len() > first {
+            lines.push((line, first..words.len()));
+        }
+    }
+
+    let matches = align_words(&normalized, &hypothesis_words);
+    let mut timings: Vec<Option<(Duration, Duration)>> = matches
+        .iter()
+        .map(|m| {
+            m.index()
+                .map(|idx| (hypothesis[idx].start_offset, hypothesis[idx].end_offset))
+        })
+        .collect();
+
+    // Spread the missing words over the gap left by their neighbours
+    let mut idx = 0;
+    while idx < timings.len() {
+        if timings[idx].is_some() {
+            idx += 1;
+            continue;
+        }
+        let gap_start = idx;
+        while idx < timings.len() && timings[idx].is_none() {
+            idx += 1;
+        }
+        let left = timings[..gap_start]
+            .last()
+            .and_then(|t| t.map(|(_, end)| end));
+        let right = timings.get(idx).and_then(|t| t.map(|(start, _)| start));
+        let (left, right) = match (left, right) {
+            (Some(left), Some(right)) => (left, right.max(left)),
+            (Some(left), None) => (left, left),
+            (None, Some(right)) => (right, right),
+            (None, None) => (Duration::ZERO, Duration::ZERO),
+        };
+        let step = (right - left) / (idx - gap_start) as u32;
+        for (n, timing) in timings[gap_start..idx].iter_mut().enumerate() {
+            let start = left + step * n as u32;
+            *timing = Some((start, start + step));
+        }
+    }
+
+    let words: Vec<AlignedWord> = words
+        .into_iter()
+        .zip(timings)
+        .zip(&matches)
+        .map(|((text, timing), matched)| {
+            let (start_offset, end_offset) = timing.unwrap_or_default();
+            AlignedWord {
+                text,
+                start_offset,
+                end_offset,
+                aligned: matches!(matched, WordMatch::Exact(_)),
+            }
+        })
+        .collect();
+
+    let segments = lines
+        .into_iter()
+        .map(|(line, range)| Segment {
+            start_offset: words[range.start].start_offset,
+            end_offset: words[range.end - 1].end_offset,
+            transcription: line.to_owned(),
+            speaker: None,
+        })
+        .collect();
+
+    let mut unaligned = Vec::new();
+    let mut idx = 0;
+    while idx < words.len() {
+        if words[idx].aligned {
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        while idx < words.len() && !words[idx].aligned {
+            idx += 1;
+        }
+        let span = &words[start..idx];
+        unaligned.push(UnalignedSpan {
+            words: start..idx,
+            text: span
+                .iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+            start_offset: span[0].start_offset,
+            end_offset: span[span.len() - 1].end_offset,
+        });
+    }
+
+    Alignment {
+        words,
+        segments,
+        unaligned,
+    }
+}
+
+/// Split segments into words, distributing each segment duration proportionally to word length
+pub(crate) fn segment_words(segments: &[Segment]) -> Vec<TimedWord> {
+    let mut words = Vec::new();
+    for segment in segments {
+        let tokens = split_words(&segment.transcription);
+        let chars: usize = tokens.iter().map(|w| w.chars().count()).sum();
+        if chars == 0 {
+            continue;
+        }
+        let span = segment.end_offset.saturating_sub(segment.start_offset);
+        let mut consumed = 0;
+        for token in tokens {
+            let len = token.chars().count();
+            let start_offset = segment.start_offset + span.mul_f64(consumed as f64 / chars as f64);
+            consumed += len;
+            let end_offset = segment.start_offset + span.mul_f64(consumed as f64 / chars as f64);
+            words.push(TimedWord {
+                text: token.to_owned(),
+                start_offset,
+                end_offset,
+            });
+        }
+    }
+    words
+}
+
+/// Words assembled from the tokens of the inference.
+///
+/// Tokens can end in the middle of a character: their bytes are kept until the
+/// character is complete, then decoded together.
+#[derive(Debug, Default)]
+pub(crate) struct WordDecoder {
+    words: Vec<TimedWord>,
+    pending: Vec<u8>,
+    start_offset: Duration,
+    end_offset: Duration,
+    new_segment: bool,
+}
+
+impl WordDecoder {
+    /// Start a new segment, its first token always starts a word
+    pub(crate) fn segment(&mut self) {
+        self.flush();
+        self.new_segment = true;
+    }
+
+    pub(crate) fn push(&mut self, bytes: &[u8], start_offset: Duration, end_offset: Duration) {
+        if self.pending.is_empty() {
+            // Skip timestamps and control tokens
+            if bytes.starts_with(b"[_") || bytes.starts_with(b"<|") {
+                return;
+            }
+            self.start_offset = start_offset;
+        }
+        self.pending.extend_from_slice(bytes);
+        self.end_offset = end_offset;
+        match std::str::from_utf8(&self.pending) {
+            // Part of a character, completed by the following tokens
+            Err(error) if error.error_len().is_none() => {}
+            _ => self.flush(),
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Vec<TimedWord> {
+        self.flush();
+        self.words
+    }
+
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        let starts_word = self.new_segment
+            || text.starts_with(' ')
+            || text.chars().next().is_some_and(is_unspaced);
+        match self.words.last_mut() {
+            Some(word) if !starts_word => {
+                word.text.push_str(&text);
+                word.end_offset = self.end_offset;
+            }
+            _ => self.words.push(TimedWord {
+                text: text.trim_start().to_owned(),
+                start_offset: self.start_offset,
+                end_offset: self.end_offset,
+            }),
+        }
+        self.new_segment = false;
+    }
+}
+
+/// Split a text in words. Scripts written without spaces are split per character.
+pub(crate) fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for chunk in text.split_whitespace() {
+        let mut start = 0;
+        for (idx, c) in chunk.char_indices() {
+            if is_unspaced(c) {
+                if start < idx {
+                    words.push(&chunk[start..idx]);
+                }
+                words.push(&chunk[idx..idx + c.len_utf8()]);
+                start = idx + c.len_utf8();
+            }
+        }
+        if start < chunk.len() {
+            words.push(&chunk[start..]);
+        }
+    }
+    words
+}
+
+/// True for characters belonging to scripts that do not separate words with spaces
+pub(crate) fn is_unspaced(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+        | 0x0E00..=0x0E7F // Thai
+        | 0x0E80..=0x0EFF // Lao
+        | 0x1000..=0x109F // Myanmar
+        | 0x1780..=0x17FF // Khmer
+        | 0x0F00..=0x0FFF // Tibetan
+    )
+}
+
+/// Lowercase a word and drop everything that is not a letter or a digit
+pub(crate) fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Pair every reference word with a hypothesis word minimizing the edit distance.
+///
+/// The dynamic programming table is restricted to a band around the diagonal,
+/// keeping memory linear in the transcript length.
+pub(crate) fn align_words(reference: &[String], hypothesis: &[String]) -> Vec<WordMatch> {
+    const DIAGONAL: u8 = 0;
+    const UP: u8 = 1;
+    const LEFT: u8 = 2;
+
+    let n = reference.len();
+    let m = hypothesis.len();
+    if n == 0 || m == 0 {
+        return vec![WordMatch::Missing; n];
+    }
+
+    let width = BAND + n.abs_diff(m);
+    let bounds = |i: usize| {
+        let center = i * m / n;
+        (center.saturating_sub(width), (center + width).min(m))
+    };
+
+    let mut rows: Vec<(usize, Vec<u32>, Vec<u8>)> = Vec::with_capacity(n + 1);
+    let (lo, hi) = bounds(0);
+    rows.push((
+        lo,
+        (lo..=hi).map(|j| j as u32).collect(),
+        vec![LEFT; hi - lo + 1],
+    ));
+
+    for i in 1..=n {
+        let (lo, hi) = bounds(i);
+        let mut costs = Vec::with_capacity(hi - lo + 1);
+        let mut moves = Vec::with_capacity(hi - lo + 1);
+        let (prev_lo, prev_costs, _) = &rows[i - 1];
+        let prev = |j: usize| {
+            j.checked_sub(*prev_lo)
+                .and_then(|k| prev_costs.get(k))
+                .copied()
+                .unwrap_or(u32::MAX)
+        };
+        for j in lo..=hi {
+            let mut best = (prev(j).saturating_add(1), UP);
+            if j > 0 {
+                let diagonal =
+                    prev(j - 1).saturating_add((reference[i - 1] != hypothesis[j - 1]) as u32);
+                if diagonal <= best.0 {
+                    best = (diagonal, DIAGONAL);
+                }
+                if j > lo {
+                    let left = costs[j - lo - 1] + 1;
+                    if left < best.0 {
+                        best = (left, LEFT);
+                    }
+                }
+            }
+            costs.push(best.0);
+            moves.push(best.1);
+        }
+        rows.push((lo, costs, moves));
+    }
+
+    let mut matches = vec![WordMatch::Missing; n];
+    let (mut i, mut j) = (n, m);
+    while i > 0 {
+        let (lo, _, moves) = &rows[i];
+        let step = j.checked_sub(*lo).and_then(|k| moves.get(k)).copied();
+        match step {
+            Some(DIAGONAL) => {
+                matches[i - 1] = if reference[i - 1] == hypothesis[j - 1] {
+                    WordMatch::Exact(j - 1)
+                } else {
+                    WordMatch::Substituted(j - 1)
+                };
+                i -= 1;
+                j -= 1;
+            }
+            Some(LEFT) if j > 0 => j -= 1,
+            _ => i -= 1,
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        split_words(text).into_iter().map(normalize).collect()
+    }
+
+    #[test]
+    fn alignment_with_edits() {
+        let reference = words("And so, my fellow Americans: ask not");
+        let hypothesis = words("and so my fellow american ask what not");
+        assert_eq!(
+            align_words(&reference, &hypothesis),
+            vec![
+                WordMatch::Exact(0),
+                WordMatch::Exact(1),
+                WordMatch::Exact(2),
+                WordMatch::Exact(3),
+                WordMatch::Substituted(4),
+                WordMatch::Exact(5),
+                WordMatch::Exact(7),
+            ]
+        );
+    }
+
+    #[test]
+    fn reference_alignment() {
+        let timed = |text: &str, start: u64, end: u64| TimedWord {
+            text: text.to_owned(),
+            start_offset: Duration::from_millis(start),
+            end_offset: Duration::from_millis(end),
+        };
+        let hypothesis = vec![
+            timed("ask", 1000, 1200),
+            timed("knot", 1200, 1400),
+            timed("what", 1400, 1600),
+            timed("country", 2400, 2800),
+        ];
+        let alignment = align_reference("Ask not what\n\nyour country", &hypothesis);
+
+        assert_eq!(alignment.segments.len(), 2);
+        assert_eq!(alignment.segments[0].transcription, "Ask not what");
+        assert_eq!(
+            alignment.segments[0].start_offset,
+            Duration::from_millis(1000)
+        );
+        assert_eq!(
+            alignment.segments[1].end_offset,
+            Duration::from_millis(2800)
+        );
+
+        // "your" is missing, it fills the gap between "what" and "country"
+        assert_eq!(alignment.words[3].start_offset, Duration::from_millis(1600));
+        assert_eq!(alignment.words[3].end_offset, Duration::from_millis(2400));
+
+        assert_eq!(
+            alignment.unaligned,
+            vec![
+                UnalignedSpan {
+                    words: 1..2,
+                    text: "not".to_owned(),
+                    start_offset: Duration::from_millis(1200),
+                    end_offset: Duration::from_millis(1400),
+                },
+                UnalignedSpan {
+                    words: 3..4,
+                    text: "your".to_owned(),
+                    start_offset: Duration::from_millis(1600),
+                    end_offset: Duration::from_millis(2400),
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn unspaced_scripts() {
+        assert_eq!(
+            split_words("今日は good day"),
+            vec!["今", "日", "は", "good", "day"]
+        );
+    }
+
+    #[test]
+    fn proportional_word_timing() {
+        let words = segment_words(&[Segment {
+            start_offset: Duration::from_secs(1),
+            end_offset: Duration::from_secs(3),
+            transcription: " ab cd".to_owned(),
+            speaker: None,
+        }]);
+        assert_eq!(words[1].start_offset, Duration::from_secs(2));
+        assert_eq!(words[1].end_offset, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn split_characters() {
+        let ms = Duration::from_millis;
+        let mut decoder = WordDecoder::default();
+        decoder.segment();
+        decoder.push(b"<|en|>", ms(0), ms(0));
+        decoder.push(b" caf", ms(0), ms(100));
+        decoder.push(b"\xC3", ms(100), ms(200));
+        decoder.push(b"\xA9", ms(200), ms(300));
+        // 日本, the first character split across two tokens
+        decoder.push(b" \xE6\x97", ms(300), ms(400));
+        decoder.push(b"\xA5\xE6\x9C\xAC", ms(400), ms(500));
+        decoder.segment();
+        decoder.push(b"\xE6", ms(600), ms(700));
+        let words = decoder.finish();
+        let text: Vec<_> = words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(text, ["café", "日本", "\u{FFFD}"]);
+        assert_eq!(words[0].end_offset, ms(300));
+        assert_eq!(words[1].start_offset, ms(300));
+        assert_eq!(words[2].start_offset, ms(600));
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::{Arc, mpsc::SyncSender};
+#[cfg(feature = "tokio")]
+use std::{collections::VecDeque, mem, sync::Mutex};
+
+use strum::{Display, EnumIs};
+#[cfg(feature = "tokio")]
+use tokio::sync::{Notify, mpsc::Sender};
+
+#[cfg(feature = "tokio")]
+use crate::ChannelObserver;
+use crate::{Error, Event, transcribe::EventSink};
+
+/// Events buffered for a consumer falling behind, unless configured otherwise
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 64;
+
+/// What the transcription does once the event channel is full.
+///
+/// Segments and errors are never dropped: they wait for the consumer to make room,
+/// pausing the inference.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Display, EnumIs)]
+pub enum Backpressure {
+    /// Every event waits for room in the channel
+    #[default]
+    Block,
+    /// [Event::DownloadProgress] events not fitting in the channel are dropped,
+    /// the download is never slowed down by the consumer
+    DropProgress,
+}
+
+impl Backpressure {
+    /// True if `event` can be dropped when the channel is full
+    fn droppable(self, event: &Result<Event, Error>) -> bool {
+        self.is_drop_progress() && matches!(event, Ok(Event::DownloadProgress { .. }))
+    }
+
+    /// Sink blocking the calling thread, failures to send to a closed channel are ignored
+    pub(crate) fn sink(self, tx: SyncSender<Result<Event, Error>>) -> EventSink {
+        Arc::new(move |event| {
+            if self.droppable(&event) {
+                let _ = tx.try_send(event);
+            } else {
+                let _ = tx.send(event);
+            }
+        })
+    }
+
+    /// Sink for the blocking tasks of the async runtime, it must not be called from async code
+    #[cfg(feature = "tokio")]
+    pub(crate) fn blocking_sink(self, tx: Sender<Result<Event, Error>>) -> EventSink {
+        Arc::new(move |event| {
+            if self.droppable(&event) {
+                let _ = tx.try_send(event);
+            } else {
+                let _ = tx.blocking_send(event);
+            }
+        })
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn send(self, tx: &Sender<Result<Event, Error>>, event: Result<Event, Error>) {
+        if self.droppable(&event) {
+            let _ = tx.try_send(event);
+        } else {
+            let _ = tx.send(event).await;
+        }
+    }
+
+    /// Relay the download events of an observer until it is dropped.
+    ///
+    /// The download runs on the async runtime and can not wait for the consumer:
+    /// with [Backpressure::Block] its events wait in [DownloadEvents] until there is room.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn forward(
+        self,
+        events: Arc<DownloadEvents>,
+        tx: &Sender<Result<Event, Error>>,
+    ) {
+        loop {
+            let (pending, closed) = events.take();
+            for event in pending {
+                self.send(tx, Ok(event)).await;
+            }
+            if closed {
+                return;
+            }
+            events.notify.notified().await;
+        }
+    }
+}
+
+/// Download events waiting to be relayed to the consumer.
+///
+/// Consecutive [Event::DownloadProgress] of a file are coalesced into the latest one,
+/// the few other events are all kept: the queue stays small however slow the consumer is.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default)]
+pub(crate) struct DownloadEvents {
+    pending: Mutex<(VecDeque<Event>, bool)>,
+    notify: Notify,
+}
+
+#[cfg(feature = "tokio")]
+impl DownloadEvents {
+    /// Observer queuing the events until it is dropped, paired with the queue to forward
+    pub(crate) fn observer() -> (ChannelObserver, Arc<Self>) {
+        let events = Arc::new(Self::default());
+        let queue = Closing(events.clone());
+        (
+            ChannelObserver::from_fn(move |event| queue.0.push(event)),
+            events,
+        )
+    }
+
+    fn push(&self, event: Event) {
+        let (queue, _) = &mut *self.pending.lock().unwrap();
+        match (queue.back_mut(), &event) {
+            (
+                Some(Event::DownloadProgress { file: last, .. }),
+                Event::DownloadProgress { file, .. },
+            ) if last == file => *queue.back_mut().unwrap() = event,
+            _ => queue.push_back(event),
+        }
+        self.notify.notify_one();
+    }
+
+    /// The queued events, and whether more can follow
+    fn take(&self) -> (VecDeque<Event>, bool) {
+        let (queue, closed) = &mut *self.pending.lock().unwrap();
+        (mem::take(queue), *closed)
+    }
+}
+
+/// Marks the queue as closed once the observer feeding it is dropped
+#[cfg(feature = "tokio")]
+struct Closing(Arc<DownloadEvents>);
+
+#[cfg(feature = "tokio")]
+impl Drop for Closing {
+    fn drop(&mut self) {
+        self.0.pending.lock().unwrap().1 = true;
+        self.0.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc::sync_channel, time::Duration};
+
+    use super::*;
+
+    fn progress(percentage: f32) -> Result<Event, Error> {
+        Ok(Event::DownloadProgress {
+            file: "model.bin".into(),
+            percentage,
+            elapsed_time: Duration::ZERO,
+            remaining_time: Duration::ZERO,
+        })
+    }
+
+    fn segment() -> Result<Event, Error> {
+        Ok(Event::Segment {
+            start_offset: Duration::ZERO,
+            end_offset: Duration::from_secs(1),
+            percentage: 1.,
+            transcription: "Hello".into(),
+        })
+    }
+
+    #[test]
+    fn drop_progress_keeps_segments() {
+        let (tx, rx) = sync_channel(1);
+        let sink = Backpressure::DropProgress.sink(tx);
+        sink(progress(10.));
+        sink(progress(20.));
+
+        let consumer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            rx.into_iter().collect::<Vec<_>>()
+        });
+        // Waits for the consumer instead of being dropped
+        sink(segment());
+        drop(sink);
+
+        let events = consumer.join().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(
+            matches!(&events[0], Ok(Event::DownloadProgress { percentage, .. }) if *percentage == 10.)
+        );
+        assert!(events[1].as_ref().is_ok_and(Event::is_segment));
+    }
+
+    #[test]
+    fn closed_channel() {
+        let (tx, rx) = sync_channel(1);
+        drop(rx);
+        let sink = Backpressure::Block.sink(tx);
+        sink(segment());
+        sink(Err(Error::Io(std::io::ErrorKind::Other.into())));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn block_keeps_progress() {
+        use crate::DownloadObserver;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let (observer, events) = DownloadEvents::observer();
+        observer.init("model.bin", 100);
+        for received in [25, 25, 50] {
+            observer.update("model.bin", received);
+        }
+        observer.finish("model.bin");
+        drop(observer);
+
+        let forwarder = tokio::spawn(async move { Backpressure::Block.forward(events, &tx).await });
+        let mut received = Vec::new();
+        while let Some(event) = rx.recv().await {
+            received.push(event.unwrap());
+        }
+        forwarder.await.unwrap();
+        // The progress waiting for the consumer is coalesced into the latest value
+        assert_eq!(received.len(), 3);
+        assert!(received[0].is_download_started());
+        assert!(
+            matches!(&received[1], Event::DownloadProgress { percentage, .. } if *percentage == 100.)
+        );
+        assert!(received[2].is_download_completed());
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    fs::{File, create_dir_all, metadata, read_to_string, remove_file, rename},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use hf_hub::{Repo, RepoType};
+use serde::{Deserialize, Serialize};
+use tar::{Archive, Builder, Header};
+
+use crate::{
+    Error, Model, ModelCache,
+    download::{link_blob, published_sha256, repo_id, sha256_file},
+};
+
+/// Name of the manifest, always the first entry of the archive
+const MANIFEST: &str = "manifest.json";
+
+/// Directory of the archive holding the model files
+const MODELS_DIR: &str = "models";
+
+/// Current layout of the bundles
+const BUNDLE_VERSION: u32 = 1;
+
+/// Content of a model bundle
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub version: u32,
+    pub models: Vec<BundledModel>,
+}
+
+/// A model file packed in a bundle, with the coordinates needed to restore it in the cache
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledModel {
+    /// The code of the model, e.g. `tiny_en`
+    pub id: String,
+    pub repo: String,
+    /// Branch or tag the model was downloaded from
+    pub reference: String,
+    /// Commit of the repository the file belongs to
+    pub revision: String,
+    pub file: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl BundledModel {
+    /// Reject entries that do not describe a built-in model or would escape the cache
+    fn validate(&self) -> Result<Model, Error> {
+        let invalid = |reason: &str| Error::Bundle(format!("{}: {reason}", self.id));
+        let model = Model::from_str(&self.id).map_err(|_| invalid("unknown model"))?;
+        let coordinates = model.hf_coordinates();
+        if self.repo != repo_id(&coordinates.repo) || self.file != coordinates.model {
+            return Err(invalid("unexpected file"));
+        }
+        if self.sha256.len() != 64 || !self.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid("invalid checksum"));
+        }
+        if !is_safe_name(&self.revision) || !is_safe_name(&self.reference) {
+            return Err(invalid("invalid revision"));
+        }
+        Ok(model)
+    }
+
+    fn entry_path(&self) -> PathBuf {
+        Path::new(MODELS_DIR).join(&self.file)
+    }
+}
+
+impl ModelCache {
+    /// Pack the cached model files and their manifest into a tar archive
+    pub fn export_bundle(&self, models: &[Model], path: &Path) -> Result<BundleManifest, Error> {
+        let mut manifest = BundleManifest {
+            version: BUNDLE_VERSION,
+            models: Vec::new(),
+        };
+        let mut files = Vec::new();
+        for model in models {
+            let code = model.info().code;
+            let cached = model
+                .cached_path(&self.config)
+                .ok_or_else(|| Error::Bundle(format!("{code} is not cached")))?;
+            let coordinates = model.hf_coordinates();
+            let repo = self.config.repo(coordinates.repo);
+            let revision = cached
+                .parent()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            manifest.models.push(BundledModel {
+                id: code,
+                repo: repo_id(&repo),
+                reference: repo.revision().to_owned(),
+                revision,
+                file: coordinates.model,
+                sha256: sha256_file(&cached)?,
+                size: metadata(&cached)?.len(),
+            });
+            files.push(cached);
+        }
+
+        let json = serde_json::to_vec_pretty(&manifest).map_err(io::Error::from)?;
+        let mut header = Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        let mut builder = Builder::new(File::create(path)?);
+        builder.append_data(&mut header, MANIFEST, json.as_slice())?;
+        for (bundled, file) in manifest.models.iter().zip(files) {
+            builder.append_file(bundled.entry_path(), &mut File::open(file)?)?;
+        }
+        builder.into_inner()?.sync_all()?;
+        Ok(manifest)
+    }
+
+    /// Unpack a bundle into the cache, checking every model file against the manifest
+    pub fn import_bundle(&self, path: &Path) -> Result<Vec<Model>, Error> {
+        let mut archive = Archive::new(File::open(path)?);
+        let mut entries = archive.entries()?;
+        let manifest: BundleManifest = match entries.next() {
+            Some(entry) => {
+                let entry = entry?;
+                if entry.path()? != Path::new(MANIFEST) {
+                    return Err(Error::Bundle(format!("{MANIFEST} must come first")));
+                }
+                serde_json::from_reader(entry).map_err(|err| Error::Bundle(err.to_string()))?
+            }
+            None => return Err(Error::Bundle("empty archive".to_owned())),
+        };
+        if manifest.version != BUNDLE_VERSION {
+            return Err(Error::Bundle(format!(
+                "unsupported version {}",
+                manifest.version
+            )));
+        }
+        let models = manifest
+            .models
+            .iter()
+            .map(BundledModel::validate)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let cache = self.config.cache();
+        let mut imported = Vec::new();
+        for entry in entries {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.into_owned();
+            let Some(index) = manifest
+                .models
+                .iter()
+                .position(|bundled| bundled.entry_path() == entry_path)
+            else {
+                return Err(Error::Bundle(format!(
+                    "unexpected entry {}",
+                    entry_path.display()
+                )));
+            };
+            let bundled = &manifest.models[index];
+            let repo = Repo::with_revision(
+                bundled.repo.clone(),
+                RepoType::Model,
+                bundled.reference.clone(),
+            );
+            // The manifest is checked against the catalogue before trusting it with the content
+            if let Some(published) = published_sha256(&repo, &bundled.file)
+                && published != bundled.sha256
+            {
+                return Err(Error::ChecksumMismatch {
+                    file: bundled.file.clone(),
+                    expected: published.to_owned(),
+                    found: bundled.sha256.clone(),
+                });
+            }
+            let repo_path = cache.path().join(repo.folder_name());
+            create_dir_all(repo_path.join("blobs"))?;
+            let blob = repo_path.join("blobs").join(&bundled.sha256);
+            let part = blob.with_extension("part");
+            io::copy(&mut entry, &mut File::create(&part)?)?;
+            let found = sha256_file(&part)?;
+            if found != bundled.sha256 {
+                remove_file(&part)?;
+                return Err(Error::ChecksumMismatch {
+                    file: bundled.file.clone(),
+                    expected: bundled.sha256.clone(),
+                    found,
+                });
+            }
+            rename(&part, &blob)?;
+
+            let pointer = repo_path
+                .join("snapshots")
+                .join(&bundled.revision)
+                .join(&bundled.file);
+            create_dir_all(pointer.parent().unwrap())?;
+            link_blob(&blob, &pointer, &bundled.file)?;
+            // Exported under a revision override, the models are still looked up from `main`
+            let mut references = vec![bundled.reference.as_str()];
+            if bundled.reference != "main" {
+                references.push("main");
+            }
+            for reference in references {
+                let commit = read_to_string(repo_path.join("refs").join(reference));
+                match commit.as_deref().map(str::trim) {
+                    // The ref keeps pointing to the models already cached, the file joins them
+                    Ok(commit) if commit != bundled.revision && is_safe_name(commit) => {
+                        let pointer = repo_path.join("snapshots").join(commit).join(&bundled.file);
+                        create_dir_all(pointer.parent().unwrap())?;
+                        link_blob(&blob, &pointer, &bundled.file)?;
+                    }
+                    Ok(_) => {}
+                    Err(_) => cache
+                        .repo(Repo::with_revision(
+                            bundled.repo.clone(),
+                            RepoType::Model,
+                            reference.to_owned(),
+                        ))
+                        .create_ref(&bundled.revision)?,
+                }
+            }
+            imported.push(models[index].clone());
+        }
+
+        if let Some(missing) = manifest
+            .models
+            .iter()
+            .zip(&models)
+            .find(|(_, model)| !imported.contains(model))
+        {
+            return Err(Error::Bundle(format!("{} is missing", missing.0.id)));
+        }
+        Ok(imported)
+    }
+}
+
+/// Pack the cached model files into a tar archive for machines without network access
+pub fn export_bundle(models: &[Model], path: &Path) -> Result<BundleManifest, Error> {
+    ModelCache::default().export_bundle(models, path)
+}
+
+/// Unpack a bundle created by [export_bundle] into the cache
+pub fn import_bundle(path: &Path) -> Result<Vec<Model>, Error> {
+    ModelCache::default().import_bundle(path)
+}
+
+/// A single path component made of plain characters
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        DownloadConfigBuilder,
+        download::tests::{COMMIT, store},
+    };
+
+    fn cache(dir: &Path) -> ModelCache {
+        ModelCache::new(
+            DownloadConfigBuilder::default()
+                .cache_dir(dir)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let (online, offline, bundles) =
+            (tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap());
+        store(online.path(), &Model::TinyEn, b"tiny weights");
+        let bundle = bundles.path().join("models.tar");
+
+        let manifest = cache(online.path())
+            .export_bundle(&[Model::TinyEn], &bundle)
+            .unwrap();
+        assert_eq!(manifest.models[0].revision, COMMIT);
+        assert_eq!(manifest.models[0].size, 12);
+        // Only cached models can be exported
+        let missing = bundles.path().join("missing.tar");
+        assert!(
+            cache(online.path())
+                .export_bundle(&[Model::Base], &missing)
+                .is_err()
+        );
+
+        let offline_cache = cache(offline.path());
+        assert!(offline_cache.list_cached().is_empty());
+        assert_eq!(
+            offline_cache.import_bundle(&bundle).unwrap(),
+            vec![Model::TinyEn]
+        );
+        let cached = offline_cache.list_cached();
+        assert_eq!(cached[0].model, Model::TinyEn);
+        assert_eq!(read(&cached[0].path).unwrap(), b"tiny weights");
+    }
+
+    #[test]
+    fn import_overridden_revision() {
+        let (online, offline, bundles) =
+            (tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap());
+        store(online.path(), &Model::TinyEn, b"tiny weights");
+        let repo = repo_id(&Model::TinyEn.hf_coordinates().repo);
+        hf_hub::Cache::new(online.path().into())
+            .repo(Repo::with_revision(repo, RepoType::Model, "v1".into()))
+            .create_ref(COMMIT)
+            .unwrap();
+        let online_cache = ModelCache::new(
+            DownloadConfigBuilder::default()
+                .cache_dir(online.path())
+                .revision("v1")
+                .build()
+                .unwrap(),
+        );
+        let bundle = bundles.path().join("models.tar");
+        let manifest = online_cache
+            .export_bundle(&[Model::TinyEn], &bundle)
+            .unwrap();
+        assert_eq!(manifest.models[0].reference, "v1");
+
+        // Found by a cache without the override
+        let offline_cache = cache(offline.path());
+        offline_cache.import_bundle(&bundle).unwrap();
+        assert_eq!(offline_cache.list_cached()[0].model, Model::TinyEn);
+    }
+
+    #[test]
+    fn import_keeps_cached_models() {
+        let (online, offline, bundles) =
+            (tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap());
+        store(online.path(), &Model::TinyEn, b"tiny weights");
+        let bundle = bundles.path().join("models.tar");
+        cache(online.path())
+            .export_bundle(&[Model::TinyEn], &bundle)
+            .unwrap();
+
+        // Base downloaded at another commit of the same repository
+        store(offline.path(), &Model::Base, b"base weights");
+        let repo_path = offline
+            .path()
+            .join(Model::Base.hf_coordinates().repo.folder_name());
+        let other = "fedcba9876543210";
+        rename(
+            repo_path.join("snapshots").join(COMMIT),
+            repo_path.join("snapshots").join(other),
+        )
+        .unwrap();
+        std::fs::write(repo_path.join("refs").join("main"), other).unwrap();
+
+        let offline_cache = cache(offline.path());
+        offline_cache.import_bundle(&bundle).unwrap();
+        let mut cached: Vec<_> = offline_cache
+            .list_cached()
+            .into_iter()
+            .map(|cached| cached.model)
+            .collect();
+        cached.sort_by_key(|model| model.to_string());
+        assert_eq!(cached, [Model::Base, Model::TinyEn]);
+        assert_eq!(
+            read_to_string(repo_path.join("refs").join("main")).unwrap(),
+            other
+        );
+    }
+
+    #[test]
+    fn reject_tampered_bundle() {
+        let (online, offline, bundles) =
+            (tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap());
+        store(online.path(), &Model::TinyEn, b"tiny weights");
+        let bundle = bundles.path().join("models.tar");
+        let mut manifest = cache(online.path())
+            .export_bundle(&[Model::TinyEn], &bundle)
+            .unwrap();
+
+        // Same archive with a manifest promising different content
+        manifest.models[0].sha256 = "0".repeat(64);
+        let json = serde_json::to_vec(&manifest).unwrap();
+        let tampered = bundles.path().join("tampered.tar");
+        let mut builder = Builder::new(File::create(&tampered).unwrap());
+        let mut header = Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, MANIFEST, json.as_slice())
+            .unwrap();
+        let mut original = Archive::new(File::open(&bundle).unwrap());
+        for entry in original.entries().unwrap().skip(1) {
+            let entry = entry.unwrap();
+            let header = entry.header().clone();
+            builder.append(&header, entry).unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+
+        let error = cache(offline.path()).import_bundle(&tampered).unwrap_err();
+        assert!(matches!(error, Error::ChecksumMismatch { .. }));
+        assert!(cache(offline.path()).list_cached().is_empty());
+
+        // Entries escaping the cache are refused before extraction
+        manifest.models[0].revision = "..".to_owned();
+        assert!(manifest.models[0].validate().is_err());
+    }
+}
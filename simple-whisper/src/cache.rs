@@ -0,0 +1,184 @@
+use std::{
+    collections::HashSet,
+    fs::{canonicalize, metadata, read_dir, remove_dir, remove_file, symlink_metadata},
+    path::PathBuf,
+};
+
+use hf_hub::{Repo, RepoType};
+use strum::IntoEnumIterator;
+
+use crate::{Error, Model, ModelSource, download::DownloadConfig};
+
+/// A built-in model available in the cache
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedModel {
+    pub model: Model,
+    pub path: PathBuf,
+    /// Size of the model file in bytes
+    pub size: u64,
+}
+
+/// Inspect and clean up the built-in models stored in the Hugging Face cache
+#[derive(Clone, Debug, Default)]
+pub struct ModelCache {
+    pub(crate) config: DownloadConfig,
+}
+
+impl ModelCache {
+    /// Manage the cache directory and revision of the download settings
+    pub fn new(config: DownloadConfig) -> Self {
+        Self { config }
+    }
+
+    /// Models available without downloading them
+    pub fn list_cached(&self) -> Vec<CachedModel> {
+        Model::iter()
+            .filter_map(|model| {
+                let path = model.cached_path(&self.config)?;
+                let size = metadata(&path).ok()?.len();
+                Some(CachedModel { model, path, size })
+            })
+            .collect()
+    }
+
+    /// Bytes used by the cached models, files shared by several revisions are counted once
+    pub fn total_size(&self) -> u64 {
+        let mut blobs = HashSet::new();
+        self.list_cached()
+            .into_iter()
+            .filter(|cached| {
+                blobs.insert(canonicalize(&cached.path).unwrap_or(cached.path.clone()))
+            })
+            .map(|cached| cached.size)
+            .sum()
+    }
+
+    /// Delete every revision of the model file, returning false if it was not cached
+    pub fn remove(&self, model: &Model) -> Result<bool, Error> {
+        let coordinates = model.hf_coordinates();
+        self.remove_file(coordinates.repo, &coordinates.model)
+    }
+
+    /// Same as [ModelCache::remove] for any model source, local files are never removed
+    pub fn remove_source(&self, source: &ModelSource) -> Result<bool, Error> {
+        match source {
+            ModelSource::Model(model) => self.remove(model),
+            ModelSource::HuggingFace {
+                repo,
+                file,
+                revision,
+            } => self.remove_file(
+                Repo::with_revision(repo.clone(), RepoType::Model, revision.clone()),
+                file,
+            ),
+            ModelSource::Registered(registered) => self.remove_source(&registered.source()),
+            ModelSource::Local(_) => Ok(false),
+        }
+    }
+
+    fn remove_file(&self, repo: Repo, file: &str) -> Result<bool, Error> {
+        let snapshots = self
+            .config
+            .cache()
+            .path()
+            .join(self.config.repo(repo).folder_name())
+            .join("snapshots");
+        let Ok(entries) = read_dir(&snapshots) else {
+            return Ok(false);
+        };
+        let mut removed = false;
+        for snapshot in entries {
+            let snapshot = snapshot?.path();
+            let pointer = snapshot.join(file);
+            let Ok(pointer_metadata) = symlink_metadata(&pointer) else {
+                continue;
+            };
+            if pointer_metadata.is_symlink()
+                && let Ok(blob) = canonicalize(&pointer)
+            {
+                remove_file(blob)?;
+            }
+            remove_file(&pointer)?;
+            // Only succeeds if no other file is left in the snapshot
+            let _ = remove_dir(&snapshot);
+            removed = true;
+        }
+        Ok(removed)
+    }
+
+    /// Delete the cached models not listed in `keep`, returning the removed ones
+    pub fn prune(&self, keep: &[Model]) -> Result<Vec<Model>, Error> {
+        let mut removed = Vec::new();
+        for model in Model::iter().filter(|model| !keep.contains(model)) {
+            if self.remove(&model)? {
+                removed.push(model);
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        DownloadConfigBuilder,
+        download::{repo_id, tests::store},
+    };
+
+    #[test]
+    fn manage_cache() {
+        let dir = tempdir().unwrap();
+        let cache = ModelCache::new(
+            DownloadConfigBuilder::default()
+                .cache_dir(dir.path())
+                .build()
+                .unwrap(),
+        );
+        assert!(cache.list_cached().is_empty());
+
+        store(dir.path(), &Model::TinyEn, b"tiny");
+        store(dir.path(), &Model::Base, b"base model");
+        store(dir.path(), &Model::Small, b"small model");
+        let cached: Vec<Model> = cache.list_cached().into_iter().map(|c| c.model).collect();
+        assert_eq!(cached, vec![Model::TinyEn, Model::Base, Model::Small]);
+        assert_eq!(cache.total_size(), 25);
+
+        assert!(cache.remove(&Model::Base).unwrap());
+        assert!(!cache.remove(&Model::Base).unwrap());
+        assert_eq!(cache.total_size(), 15);
+
+        assert_eq!(cache.prune(&[Model::TinyEn]).unwrap(), vec![Model::Small]);
+        let cached: Vec<Model> = cache.list_cached().into_iter().map(|c| c.model).collect();
+        assert_eq!(cached, vec![Model::TinyEn]);
+        let blobs = dir
+            .path()
+            .join(Model::TinyEn.hf_coordinates().repo.folder_name())
+            .join("blobs");
+        assert_eq!(read_dir(blobs).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn remove_sources() {
+        let dir = tempdir().unwrap();
+        let cache = ModelCache::new(
+            DownloadConfigBuilder::default()
+                .cache_dir(dir.path())
+                .build()
+                .unwrap(),
+        );
+        store(dir.path(), &Model::TinyEn, b"tiny");
+        let coordinates = Model::TinyEn.hf_coordinates();
+        let source = ModelSource::hugging_face(repo_id(&coordinates.repo), coordinates.model);
+        assert!(cache.remove_source(&source).unwrap());
+        assert!(cache.list_cached().is_empty());
+        assert!(!cache.remove_source(&source).unwrap());
+        assert!(
+            !cache
+                .remove_source(&ModelSource::Local("ggml-model.bin".into()))
+                .unwrap()
+        );
+    }
+}
@@ -1,148 +1,434 @@
 use std::{
-    path::PathBuf,
-    sync::{Arc, Mutex},
-    time::Instant,
+    env,
+    fs::{read_link, read_to_string},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    time::Duration,
 };
 
-use hf_hub::{Cache, Repo, api::tokio::Progress};
-use tokio::sync::mpsc::UnboundedSender;
+use derive_builder::Builder;
+use hf_hub::{Cache, Repo, RepoType};
+#[cfg(feature = "tokio")]
+use reqwest::Proxy;
+use sha2::{Digest, Sha256};
+use strum::IntoEnumIterator;
 
-use crate::{Error, Event};
+#[cfg(not(feature = "tokio"))]
+use crate::{DownloadObserver, Event};
+use crate::{Error, Model};
 
-/// Store the state of a download
-#[derive(Debug, Clone)]
-struct DownloadState {
-    start_time: Instant,
-    len: usize,
-    offset: usize,
-    url: String,
+/// Hub used when `HF_ENDPOINT` is not set
+const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
+/// Where and how model files are downloaded.
+///
+/// Unset fields follow the Hugging Face conventions: `HF_ENDPOINT`, `HF_HOME` and `HF_TOKEN`
+/// environment variables, then the token stored by `huggingface-cli login`.
+/// Without an explicit proxy the `HTTPS_PROXY`/`ALL_PROXY` variables are honored.
+#[derive(Builder, Clone, Debug, PartialEq, Eq)]
+#[builder(
+    setter(into, strip_option),
+    default,
+    build_fn(validate = "Self::validate")
+)]
+pub struct DownloadConfig {
+    /// Base URL of the Hugging Face hub or of a mirror
+    pub(crate) endpoint: String,
+    /// Root of the model cache
+    pub(crate) cache_dir: PathBuf,
+    /// Token to access private or gated repositories
+    pub(crate) token: Option<String>,
+    /// Branch, tag or commit overriding the revision of the model source
+    revision: Option<String>,
+    /// Proxy URL used for every request.
+    /// The blocking downloads of the `ureq` feature only honor the proxy environment variables
+    pub(crate) proxy: Option<String>,
+    /// Check the SHA-256 of cached files too, not only of the downloaded ones.
+    /// Cached files without a known SHA-256 fail with [Error::Unverifiable]
+    pub(crate) verify_cache: bool,
+    /// Delete and download again files failing the integrity check
+    pub(crate) redownload_corrupted: bool,
+    /// Attempts to resume an interrupted transfer before giving up
+    pub(crate) retries: u32,
+    /// Wait before the first retry, doubled at every attempt
+    pub(crate) retry_backoff: Duration,
 }
 
-impl DownloadState {
-    fn new(len: usize, url: &str) -> DownloadState {
-        DownloadState {
-            start_time: Instant::now(),
-            len,
-            offset: 0,
-            url: url.to_string(),
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        let cache = Cache::from_env();
+        Self {
+            endpoint: env::var("HF_ENDPOINT").unwrap_or_else(|_| DEFAULT_ENDPOINT.to_owned()),
+            token: env::var("HF_TOKEN").ok().or_else(|| cache.token()),
+            cache_dir: cache.path().clone(),
+            revision: None,
+            proxy: None,
+            verify_cache: false,
+            redownload_corrupted: false,
+            retries: 5,
+            retry_backoff: Duration::from_secs(1),
         }
     }
+}
 
-    fn update(&mut self, delta: usize) -> Option<Event> {
-        if delta == 0 {
-            return None;
+impl DownloadConfigBuilder {
+    fn validate(&self) -> Result<(), DownloadConfigBuilderError> {
+        // Proxies are only used by the downloads
+        #[cfg(feature = "tokio")]
+        if let Some(Some(proxy)) = &self.proxy {
+            Proxy::all(proxy).map_err(|err| {
+                DownloadConfigBuilderError::ValidationError(format!("Invalid proxy {proxy}: {err}"))
+            })?;
         }
+        Ok(())
+    }
+}
 
-        self.offset += delta;
+impl DownloadConfig {
+    pub(crate) fn cache(&self) -> Cache {
+        Cache::new(self.cache_dir.clone())
+    }
 
-        let elapsed_time = Instant::now() - self.start_time;
+    /// Apply the revision override, only model repositories are downloaded
+    pub(crate) fn repo(&self, repo: Repo) -> Repo {
+        match &self.revision {
+            Some(revision) => {
+                Repo::with_revision(repo_id(&repo), RepoType::Model, revision.clone())
+            }
+            None => repo,
+        }
+    }
+}
 
-        let progress = self.offset as f32 / self.len as f32;
-        let progress_100 = progress * 100.;
+/// The `owner/name` id of a model repository.
+///
+/// `Repo::url` is only built with the hf-hub network features, the hub forbids `--`
+/// in the repository names so the cache folder name can be mapped back.
+pub(crate) fn repo_id(repo: &Repo) -> String {
+    let folder = repo.folder_name();
+    folder
+        .strip_prefix("models--")
+        .unwrap_or(&folder)
+        .replace("--", "/")
+}
 
-        let remaining_percentage = 100. - progress_100;
-        let duration_unit = elapsed_time
-            / if progress_100 as u32 == 0 {
-                1
-            } else {
-                progress_100 as u32
-            };
-        let remaining_time = duration_unit * remaining_percentage as u32;
+/// True if the `HF_HUB_OFFLINE` environment variable is set
+pub fn offline_from_env() -> bool {
+    env::var("HF_HUB_OFFLINE").is_ok_and(|value| {
+        matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
 
-        let event = Event::DownloadProgress {
-            file: self.url.clone(),
-            percentage: progress_100,
-            elapsed_time,
-            remaining_time,
-        };
-        Some(event)
-    }
+/// Location where the file would be stored by the Hugging Face cache
+pub(crate) fn expected_path(cache: &Cache, repo: &Repo, file: &str) -> PathBuf {
+    let repo_path = cache.path().join(repo.folder_name());
+    let revision = read_to_string(repo_path.join("refs").join(repo.revision()))
+        .map(|commit| commit.trim().to_owned())
+        .unwrap_or_else(|_| repo.revision().to_owned());
+    repo_path.join("snapshots").join(revision).join(file)
 }
 
-#[derive(Clone)]
-struct DownloadCallback {
-    download_state: Arc<Mutex<Option<DownloadState>>>,
-    tx: UnboundedSender<Event>,
+/// Files stored with Git LFS are addressed by the SHA-256 of their content
+pub(crate) fn lfs_sha256(etag: &str) -> Option<&str> {
+    (etag.len() == 64 && etag.bytes().all(|b| b.is_ascii_hexdigit())).then_some(etag)
 }
 
-impl Progress for DownloadCallback {
-    async fn init(&mut self, len: usize, file: &str) {
-        self.download_state = Arc::new(Mutex::new(Some(DownloadState::new(len, file))));
+/// SHA-256 naming the blob a cached file points to.
+///
+/// Files that are not LFS blobs, or copied out of the blob store, can not be verified.
+fn blob_sha256(path: &Path) -> Option<String> {
+    read_link(path).ok().and_then(|target| {
+        target
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(lfs_sha256)
+            .map(str::to_owned)
+    })
+}
 
-        let _ = self.tx.send(Event::DownloadStarted {
-            file: file.to_owned(),
-        });
+/// SHA-256 recorded for a file of the model catalogue, only known for the `main` revision
+pub(crate) fn published_sha256(repo: &Repo, file: &str) -> Option<&'static str> {
+    if repo.revision() != "main" {
+        return None;
     }
+    Model::iter()
+        .find(|model| {
+            let coordinates = model.hf_coordinates();
+            coordinates.model == file && repo_id(&coordinates.repo) == repo_id(repo)
+        })?
+        .sha256()
+}
 
-    async fn update(&mut self, delta: usize) {
-        let update = self
-            .download_state
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .update(delta);
-        if let Some(event) = update {
-            let _ = self.tx.send(event);
-        }
+/// Check a cached file against the published SHA-256 of the model, or the one naming its blob.
+///
+/// A file known by neither, whose content is not a blob of the repository, can not be trusted.
+pub(crate) fn verify_cached_file(file: &str, repo: &Repo, path: &Path) -> Result<(), Error> {
+    let expected = published_sha256(repo, file)
+        .map(str::to_owned)
+        .or_else(|| blob_sha256(path));
+    if let Some(expected) = expected {
+        return verify_blob(file, path, expected);
     }
-
-    async fn finish(&mut self) {
-        let file = self
-            .download_state
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .url
-            .clone();
-        let _ = self.tx.send(Event::DownloadCompleted { file });
+    // Copied out of the blob store where symlinks are not available, the blob is kept next to it
+    let found = sha256_file(path)?;
+    let folder = repo.folder_name();
+    let stored = path
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| *name == *folder))
+        .is_some_and(|dir| dir.join("blobs").join(&found).is_file());
+    if stored {
+        Ok(())
+    } else {
+        Err(Error::Unverifiable {
+            file: file.to_owned(),
+            path: path.to_owned(),
+        })
     }
 }
 
-pub enum ProgressType {
-    Callback(UnboundedSender<Event>),
-    ProgressBar,
+/// Check the content of a cached file against the SHA-256 of its blob
+fn verify_blob(file: &str, path: &Path, expected: String) -> Result<(), Error> {
+    let found = sha256_file(path)?;
+    if found == expected {
+        Ok(())
+    } else {
+        Err(Error::ChecksumMismatch {
+            file: file.to_owned(),
+            expected,
+            found,
+        })
+    }
 }
 
-pub async fn download_file(
+/// Blocking resolution of a hub file, used when the `tokio` feature is disabled.
+///
+/// Files missing from the cache are downloaded with the `ureq` feature, without it
+/// [Error::DownloadsDisabled] is returned.
+#[cfg(not(feature = "tokio"))]
+pub(crate) fn blocking_file(
     file: &str,
     force_download: bool,
-    progress: ProgressType,
+    offline: bool,
+    observer: &dyn DownloadObserver,
     repo: Repo,
+    config: &DownloadConfig,
 ) -> Result<PathBuf, Error> {
-    let cache = Cache::from_env().repo(repo.clone());
-    let mut in_cache = cache.get(file);
-    if force_download {
+    let cache = config.cache();
+    let repo = config.repo(repo);
+    let mut in_cache = cache.repo(repo.clone()).get(file);
+    if force_download && !offline {
         in_cache = None
     }
-    if let Some(val) = in_cache {
-        Ok(val)
-    } else {
-        match progress {
-            ProgressType::ProgressBar => {
-                hf_hub::api::tokio::ApiBuilder::default()
-                    .with_progress(true)
-                    .build()
-                    .map(|api| api.repo(repo))
-                    .map_err(Into::<Error>::into)?
-                    .download(file)
-                    .await
-            }
-            ProgressType::Callback(tx) => {
-                let progress = DownloadCallback {
-                    download_state: Default::default(),
-                    tx,
-                };
-                hf_hub::api::tokio::ApiBuilder::default()
-                    .with_progress(false)
-                    .build()
-                    .map(|api| api.repo(repo))
-                    .map_err(Into::<Error>::into)?
-                    .download_with_progress(file, progress)
-                    .await
+    if let Some(path) = in_cache {
+        if !config.verify_cache {
+            return Ok(path);
+        }
+        observer.event(&Event::Verifying {
+            file: file.to_owned(),
+        });
+        match verify_cached_file(file, &repo, &path) {
+            Err(Error::ChecksumMismatch { .. }) if config.redownload_corrupted && !offline => {
+                remove_cached(&path)?
             }
+            verified => return verified.map(|_| path),
+        }
+    } else if offline {
+        return Err(Error::ModelNotAvailableOffline {
+            file: file.to_owned(),
+            path: expected_path(&cache, &repo, file),
+        });
+    }
+
+    #[cfg(feature = "ureq")]
+    let downloaded = match blocking_fetch(file, &repo, observer, config) {
+        Err(Error::ChecksumMismatch { .. }) if config.redownload_corrupted => {
+            blocking_fetch(file, &repo, observer, config)
         }
-        .map_err(Into::into)
+        fetched => fetched,
+    };
+    #[cfg(not(feature = "ureq"))]
+    let downloaded = Err(Error::DownloadsDisabled {
+        file: file.to_owned(),
+        path: expected_path(&cache, &repo, file),
+    });
+    if let Err(err) = &downloaded {
+        observer.error(file, err);
+    }
+    downloaded
+}
+
+/// Download the file with the synchronous hub client, then check it like the async downloads
+#[cfg(all(feature = "ureq", not(feature = "tokio")))]
+fn blocking_fetch(
+    file: &str,
+    repo: &Repo,
+    observer: &dyn DownloadObserver,
+    config: &DownloadConfig,
+) -> Result<PathBuf, Error> {
+    let api = hf_hub::api::sync::ApiBuilder::from_cache(config.cache())
+        .with_endpoint(config.endpoint.clone())
+        .with_token(config.token.clone())
+        .with_retries(config.retries as usize)
+        .with_progress(false)
+        .build()?;
+    let path = api
+        .repo(repo.clone())
+        .download_with_progress(file, ObserverProgress { file, observer })?;
+    let expected = published_sha256(repo, file)
+        .map(str::to_owned)
+        .or_else(|| blob_sha256(&path));
+    if let Some(expected) = expected {
+        observer.event(&Event::Verifying {
+            file: file.to_owned(),
+        });
+        if let Err(err) = verify_blob(file, &path, expected) {
+            remove_cached(&path)?;
+            return Err(err);
+        }
+    }
+    Ok(path)
+}
+
+/// Progress of the synchronous hub client reported to a [DownloadObserver]
+#[cfg(all(feature = "ureq", not(feature = "tokio")))]
+struct ObserverProgress<'a> {
+    file: &'a str,
+    observer: &'a dyn DownloadObserver,
+}
+
+#[cfg(all(feature = "ureq", not(feature = "tokio")))]
+impl hf_hub::api::Progress for ObserverProgress<'_> {
+    fn init(&mut self, size: usize, _filename: &str) {
+        self.observer.init(self.file, size as u64);
+    }
+
+    fn update(&mut self, size: usize) {
+        self.observer.update(self.file, size as u64);
+    }
+
+    fn finish(&mut self) {
+        self.observer.finish(self.file);
+    }
+}
+
+/// Delete the snapshot entry together with the blob it points to
+pub(crate) fn remove_cached(path: &Path) -> io::Result<()> {
+    if let Ok(blob) = std::fs::canonicalize(path) {
+        std::fs::remove_file(blob)?;
+    }
+    std::fs::remove_file(path)
+}
+
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    update_hasher(&mut hasher, std::fs::File::open(path)?)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+pub(crate) fn update_hasher(hasher: &mut Sha256, mut reader: impl Read) -> io::Result<()> {
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok(()),
+            n => hasher.update(&buf[..n]),
+        }
+    }
+}
+
+pub(crate) fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Point the snapshot entry to the blob, falling back to a copy where symlinks are not allowed
+pub(crate) fn link_blob(blob: &Path, pointer: &Path, file: &str) -> std::io::Result<()> {
+    if pointer.exists() {
+        std::fs::remove_file(pointer)?;
+    }
+    // From snapshots/<commit>/<file> back to the repository folder
+    let depth = Path::new(file)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .count()
+        + 1;
+    let mut target: PathBuf = (0..depth).map(|_| Component::ParentDir).collect();
+    target.push("blobs");
+    target.push(blob.file_name().unwrap());
+
+    #[cfg(unix)]
+    let linked = std::os::unix::fs::symlink(&target, pointer);
+    #[cfg(windows)]
+    let linked = std::os::windows::fs::symlink_file(&target, pointer);
+    #[cfg(not(any(unix, windows)))]
+    let linked: std::io::Result<()> = Err(std::io::ErrorKind::Unsupported.into());
+
+    if linked.is_err() {
+        std::fs::copy(blob, pointer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    pub(crate) const COMMIT: &str = "0123456789abcdef";
+
+    /// Lay out the model file in the cache as a download would
+    pub(crate) fn store(dir: &Path, model: &Model, content: &[u8]) {
+        let coordinates = model.hf_coordinates();
+        let repo_path = dir.join(coordinates.repo.folder_name());
+        let blob = repo_path.join("blobs").join(hex(&Sha256::digest(content)));
+        let pointer = repo_path
+            .join("snapshots")
+            .join(COMMIT)
+            .join(&coordinates.model);
+        create_dir_all(blob.parent().unwrap()).unwrap();
+        create_dir_all(pointer.parent().unwrap()).unwrap();
+        write(&blob, content).unwrap();
+        link_blob(&blob, &pointer, &coordinates.model).unwrap();
+        Cache::new(dir.into())
+            .repo(coordinates.repo)
+            .create_ref(COMMIT)
+            .unwrap();
+    }
+
+    #[test]
+    fn repo_id_from_folder() {
+        let repo = Repo::with_revision("owner/model".into(), RepoType::Model, "main".into());
+        assert_eq!(repo_id(&repo), "owner/model");
+        let config = DownloadConfig {
+            revision: Some("v2".into()),
+            ..Default::default()
+        };
+        let repo = config.repo(repo);
+        assert_eq!(repo_id(&repo), "owner/model");
+        assert_eq!(repo.revision(), "v2");
+    }
+
+    #[cfg(not(any(feature = "tokio", feature = "ureq")))]
+    #[test]
+    fn blocking_without_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DownloadConfig {
+            cache_dir: dir.path().into(),
+            ..Default::default()
+        };
+        let observer: Vec<std::sync::Arc<dyn DownloadObserver>> = Vec::new();
+        let resolve = |model: &Model| {
+            let coordinates = model.hf_coordinates();
+            let repo = coordinates.repo;
+            blocking_file(&coordinates.model, false, false, &observer, repo, &config)
+        };
+        store(dir.path(), &Model::TinyEn, b"tiny weights");
+        assert!(resolve(&Model::TinyEn).is_ok());
+        assert!(matches!(
+            resolve(&Model::Base),
+            Err(Error::DownloadsDisabled { .. })
+        ));
     }
 }
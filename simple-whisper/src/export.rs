@@ -0,0 +1,624 @@
+use std::{fmt::Write, time::Duration};
+
+use derive_builder::Builder;
+use strum::{Display, EnumIter, EnumString};
+
+use crate::{
+    Language, Segment,
+    group::{PARAGRAPH_PAUSE, group_paragraphs},
+};
+
+/// Transcript output formats
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, EnumIter, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ExportFormat {
+    /// Plain text, one segment per line.
+    #[default]
+    Text,
+    /// Audacity label track (tab separated start, end and label).
+    Audacity,
+    /// CMX3600 edit decision list carrying one locator per segment.
+    Edl,
+    /// Final Cut Pro XML (fcpxml 1.9) marker list.
+    Fcpxml,
+    /// Final Cut Pro 7 XML (xmeml) marker list, as imported by Premiere Pro.
+    PremiereXml,
+    /// Self-contained HTML page with clickable timestamps and an audio player.
+    Html,
+    /// Markdown document with a timestamp heading per paragraph.
+    Markdown,
+    /// SubRip subtitles.
+    Srt,
+    /// WebVTT subtitles, speakers are written as voice tags.
+    Vtt,
+}
+
+impl ExportFormat {
+    /// The file extension commonly associated to the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Audacity => "txt",
+            ExportFormat::Edl => "edl",
+            ExportFormat::Fcpxml => "fcpxml",
+            ExportFormat::PremiereXml => "xml",
+            ExportFormat::Html => "html",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Srt => "srt",
+            ExportFormat::Vtt => "vtt",
+        }
+    }
+}
+
+/// Converts transcript segments into formats understood by editing software.
+#[derive(Builder, Clone, Debug)]
+#[builder(setter(into), build_fn(validate = "Self::validate"))]
+pub struct Exporter {
+    #[builder(default)]
+    format: ExportFormat,
+    /// Name given to the timeline/sequence by the formats supporting it
+    #[builder(default = "\"Transcript\".to_owned()")]
+    title: String,
+    /// Timeline frame rate used by the frame based formats
+    #[builder(default = "25")]
+    frame_rate: u32,
+    /// Path of the audio file played by the HTML player, usually the transcribed file
+    #[builder(default, setter(into, strip_option))]
+    audio: Option<String>,
+    /// Language of the transcript, tagging the HTML page and the WebVTT header
+    #[builder(default, setter(into, strip_option))]
+    language: Option<Language>,
+}
+
+impl ExporterBuilder {
+    fn validate(&self) -> Result<(), ExporterBuilderError> {
+        if self.frame_rate.is_some_and(|fps| fps == 0) {
+            return Err(ExporterBuilderError::ValidationError(
+                "The frame rate must be greater than 0".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Exporter {
+    /// Render the segments in the configured format.
+    pub fn export(&self, segments: &[Segment]) -> String {
+        match self.format {
+            ExportFormat::Text => segments
+                .iter()
+                .map(|s| s.transcription.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ExportFormat::Audacity => self.audacity(segments),
+            ExportFormat::Edl => self.edl(segments),
+            ExportFormat::Fcpxml => self.fcpxml(segments),
+            ExportFormat::PremiereXml => self.premiere_xml(segments),
+            ExportFormat::Html => self.html(segments),
+            ExportFormat::Markdown => self.markdown(segments),
+            ExportFormat::Srt => self.srt(segments),
+            ExportFormat::Vtt => self.vtt(segments),
+        }
+    }
+
+    fn audacity(&self, segments: &[Segment]) -> String {
+        let mut out = String::new();
+        for segment in segments {
+            let _ = writeln!(
+                out,
+                "{:.6}\t{:.6}\t{}",
+                segment.start_offset.as_secs_f64(),
+                segment.end_offset.as_secs_f64(),
+                single_line(&segment.transcription)
+            );
+        }
+        out
+    }
+
+    fn edl(&self, segments: &[Segment]) -> String {
+        let mut out = format!(
+            "TITLE: {}\nFCM: NON-DROP FRAME\n\n",
+            single_line(&self.title)
+        );
+        for (idx, segment) in segments.iter().enumerate() {
+            let (start, end) = self.frame_span(segment);
+            let start = self.timecode(start);
+            let end = self.timecode(end);
+            let _ = writeln!(
+                out,
+                "{:03}  AX       V     C        {start} {end} {start} {end}",
+                idx + 1
+            );
+            let _ = writeln!(
+                out,
+                "* LOC: {start} WHITE   {}\n",
+                single_line(&segment.transcription)
+            );
+        }
+        out
+    }
+
+    fn fcpxml(&self, segments: &[Segment]) -> String {
+        let fps = self.frame_rate;
+        let duration = self.total_frames(segments);
+        let title = escape_xml(&self.title);
+        let mut out = String::new();
+        let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(out, "<!DOCTYPE fcpxml>");
+        let _ = writeln!(out, r#"<fcpxml version="1.9">"#);
+        let _ = writeln!(out, "  <resources>");
+        let _ = writeln!(
+            out,
+            r#"    <format id="r1" name="FFVideoFormat{fps}p" frameDuration="1/{fps}s"/>"#
+        );
+        let _ = writeln!(out, "  </resources>");
+        let _ = writeln!(out, "  <library>");
+        let _ = writeln!(out, r#"    <event name="{title}">"#);
+        let _ = writeln!(out, r#"      <project name="{title}">"#);
+        let _ = writeln!(
+            out,
+            r#"        <sequence format="r1" duration="{duration}/{fps}s" tcStart="0s" tcFormat="NDF">"#
+        );
+        let _ = writeln!(out, "          <spine>");
+        let _ = writeln!(
+            out,
+            r#"            <gap name="{title}" offset="0s" start="0s" duration="{duration}/{fps}s">"#
+        );
+        for segment in segments {
+            let (start, end) = self.frame_span(segment);
+            let _ = writeln!(
+                out,
+                r#"              <marker start="{start}/{fps}s" duration="{}/{fps}s" value="{}"/>"#,
+                end - start,
+                escape_xml(&single_line(&segment.transcription))
+            );
+        }
+        let _ = writeln!(out, "            </gap>");
+        let _ = writeln!(out, "          </spine>");
+        let _ = writeln!(out, "        </sequence>");
+        let _ = writeln!(out, "      </project>");
+        let _ = writeln!(out, "    </event>");
+        let _ = writeln!(out, "  </library>");
+        let _ = writeln!(out, "</fcpxml>");
+        out
+    }
+
+    fn premiere_xml(&self, segments: &[Segment]) -> String {
+        let fps = self.frame_rate;
+        let mut out = String::new();
+        let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(out, "<!DOCTYPE xmeml>");
+        let _ = writeln!(out, r#"<xmeml version="4">"#);
+        let _ = writeln!(out, "  <sequence>");
+        let _ = writeln!(out, "    <name>{}</name>", escape_xml(&self.title));
+        let _ = writeln!(
+            out,
+            "    <duration>{}</duration>",
+            self.total_frames(segments)
+        );
+        let _ = writeln!(out, "    <rate>");
+        let _ = writeln!(out, "      <timebase>{fps}</timebase>");
+        let _ = writeln!(out, "      <ntsc>FALSE</ntsc>");
+        let _ = writeln!(out, "    </rate>");
+        for segment in segments {
+            let (start, end) = self.frame_span(segment);
+            let _ = writeln!(out, "    <marker>");
+            let _ = writeln!(
+                out,
+                "      <name>{}</name>",
+                escape_xml(&single_line(&segment.transcription))
+            );
+            let _ = writeln!(out, "      <comment></comment>");
+            let _ = writeln!(out, "      <in>{start}</in>");
+            let _ = writeln!(out, "      <out>{end}</out>");
+            let _ = writeln!(out, "    </marker>");
+        }
+        let _ = writeln!(out, "  </sequence>");
+        let _ = writeln!(out, "</xmeml>");
+        out
+    }
+
+    fn html(&self, segments: &[Segment]) -> String {
+        let title = escape_xml(&self.title);
+        let mut out = String::new();
+        let _ = writeln!(out, "<!DOCTYPE html>");
+        match self.language {
+            Some(language) if language.is_rtl() => {
+                let _ = writeln!(out, r#"<html lang="{}" dir="rtl">"#, language.bcp47());
+            }
+            Some(language) => {
+                let _ = writeln!(out, r#"<html lang="{}">"#, language.bcp47());
+            }
+            None => {
+                let _ = writeln!(out, "<html>");
+            }
+        }
+        let _ = writeln!(out, "<head>");
+        let _ = writeln!(out, r#"<meta charset="utf-8">"#);
+        let _ = writeln!(
+            out,
+            r#"<meta name="viewport" content="width=device-width, initial-scale=1">"#
+        );
+        let _ = writeln!(out, "<title>{title}</title>");
+        let _ = writeln!(out, "<style>");
+        let _ = writeln!(
+            out,
+            "body {{ font-family: sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.6; }}"
+        );
+        let _ = writeln!(
+            out,
+            "audio {{ position: sticky; top: 0; width: 100%; background: #fff; }}"
+        );
+        let _ = writeln!(
+            out,
+            ".timestamp {{ color: #666; font-size: 0.85em; margin-right: 0.5em; text-decoration: none; }}"
+        );
+        let _ = writeln!(
+            out,
+            ".speaker {{ font-weight: bold; margin-right: 0.5em; }}"
+        );
+        let _ = writeln!(out, "[data-start] {{ cursor: pointer; }}");
+        let _ = writeln!(out, "[data-start]:hover {{ background: #ffc; }}");
+        let _ = writeln!(out, "</style>");
+        let _ = writeln!(out, "</head>");
+        let _ = writeln!(out, "<body>");
+        let _ = writeln!(out, "<h1>{title}</h1>");
+        if let Some(audio) = &self.audio {
+            let _ = writeln!(
+                out,
+                r#"<audio id="player" controls preload="metadata" src="{}"></audio>"#,
+                escape_xml(&encode_path(audio))
+            );
+        }
+        let _ = writeln!(out, "<article>");
+        for paragraph in group_paragraphs(segments, PARAGRAPH_PAUSE) {
+            let start = paragraph.start_offset;
+            let _ = write!(out, "<p>");
+            let _ = write!(
+                out,
+                r##"<a class="timestamp" href="#t={:.2}" data-start="{:.2}">{}</a>"##,
+                start.as_secs_f64(),
+                start.as_secs_f64(),
+                clock(start)
+            );
+            if let Some(speaker) = &paragraph.speaker {
+                let _ = write!(
+                    out,
+                    r#"<span class="speaker">{}</span>"#,
+                    escape_xml(speaker)
+                );
+            }
+            for segment in &paragraph.segments {
+                let _ = write!(
+                    out,
+                    r#"<span class="segment" data-start="{:.2}">{} </span>"#,
+                    segment.start_offset.as_secs_f64(),
+                    escape_xml(segment.transcription.trim())
+                );
+            }
+            let _ = writeln!(out, "</p>");
+        }
+        let _ = writeln!(out, "</article>");
+        let _ = writeln!(out, "<script>");
+        let _ = writeln!(out, r#"const player = document.getElementById("player");"#);
+        let _ = writeln!(
+            out,
+            r#"document.querySelectorAll("[data-start]").forEach((el) => el.addEventListener("click", (event) => {{"#
+        );
+        let _ = writeln!(out, "  event.preventDefault();");
+        let _ = writeln!(out, "  if (!player) return;");
+        let _ = writeln!(out, "  player.currentTime = parseFloat(el.dataset.start);");
+        let _ = writeln!(out, "  player.play();");
+        let _ = writeln!(out, "}}));");
+        let _ = writeln!(out, "</script>");
+        let _ = writeln!(out, "</body>");
+        let _ = writeln!(out, "</html>");
+        out
+    }
+
+    fn markdown(&self, segments: &[Segment]) -> String {
+        let mut out = format!("# {}\n", single_line(&self.title));
+        if let Some(audio) = &self.audio {
+            let _ = writeln!(out, "\nAudio: <{audio}>");
+        }
+        for paragraph in group_paragraphs(segments, PARAGRAPH_PAUSE) {
+            let start = clock(paragraph.start_offset);
+            match &paragraph.speaker {
+                Some(speaker) => {
+                    let _ = writeln!(out, "\n## [{start}] {}\n", single_line(speaker));
+                }
+                None => {
+                    let _ = writeln!(out, "\n## [{start}]\n");
+                }
+            }
+            let text = paragraph
+                .segments
+                .iter()
+                .map(|s| single_line(&s.transcription))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(out, "{text}");
+        }
+        out
+    }
+
+    fn srt(&self, segments: &[Segment]) -> String {
+        let mut out = String::new();
+        for (idx, segment) in segments.iter().enumerate() {
+            let _ = writeln!(out, "{}", idx + 1);
+            let _ = writeln!(
+                out,
+                "{} --> {}",
+                subtitle_timestamp(segment.start_offset, ','),
+                subtitle_timestamp(segment.end_offset, ',')
+            );
+            let _ = writeln!(out, "{}\n", segment.transcription.trim());
+        }
+        out
+    }
+
+    fn vtt(&self, segments: &[Segment]) -> String {
+        let mut out = "WEBVTT\n".to_owned();
+        if let Some(language) = self.language {
+            let _ = writeln!(out, "Language: {}", language.bcp47());
+        }
+        out.push('\n');
+        for segment in segments {
+            let _ = writeln!(
+                out,
+                "{} --> {}",
+                subtitle_timestamp(segment.start_offset, '.'),
+                subtitle_timestamp(segment.end_offset, '.')
+            );
+            let text = segment
+                .transcription
+                .trim()
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            match &segment.speaker {
+                Some(speaker) => {
+                    let _ = writeln!(out, "<v {}>{text}\n", single_line(speaker));
+                }
+                None => {
+                    let _ = writeln!(out, "{text}\n");
+                }
+            }
+        }
+        out
+    }
+
+    fn frames(&self, offset: Duration) -> u64 {
+        (offset.as_secs_f64() * self.frame_rate as f64).round() as u64
+    }
+
+    /// Segment boundaries in frames, markers last at least one frame
+    fn frame_span(&self, segment: &Segment) -> (u64, u64) {
+        let start = self.frames(segment.start_offset);
+        let end = self.frames(segment.end_offset).max(start + 1);
+        (start, end)
+    }
+
+    fn total_frames(&self, segments: &[Segment]) -> u64 {
+        segments
+            .iter()
+            .map(|s| self.frame_span(s).1)
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn timecode(&self, frames: u64) -> String {
+        let fps = self.frame_rate as u64;
+        let secs = frames / fps;
+        format!(
+            "{:02}:{:02}:{:02}:{:02}",
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60,
+            frames % fps
+        )
+    }
+}
+
+/// Format an offset as HH:MM:SS
+fn clock(offset: Duration) -> String {
+    let secs = offset.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+/// Format an offset as HH:MM:SS followed by the milliseconds
+fn subtitle_timestamp(offset: Duration, separator: char) -> String {
+    format!("{}{separator}{:03}", clock(offset), offset.subsec_millis())
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Percent-encode a file path to be used as a relative URL, keeping its separators
+fn encode_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => {
+                let _ = write!(out, "%{byte:02X}");
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments() -> Vec<Segment> {
+        vec![
+            Segment {
+                start_offset: Duration::from_millis(0),
+                end_offset: Duration::from_millis(1500),
+                transcription: " And so my fellow Americans".to_owned(),
+                speaker: None,
+            },
+            Segment {
+                start_offset: Duration::from_secs(3660),
+                end_offset: Duration::from_millis(3_662_040),
+                transcription: " ask <not>\twhat".to_owned(),
+                speaker: Some("Kennedy".to_owned()),
+            },
+        ]
+    }
+
+    #[test]
+    fn audacity_labels() {
+        let out = ExporterBuilder::default()
+            .format(ExportFormat::Audacity)
+            .build()
+            .unwrap()
+            .export(&segments());
+        assert_eq!(
+            out,
+            "0.000000\t1.500000\tAnd so my fellow Americans\n3660.000000\t3662.040000\task <not> what\n"
+        );
+    }
+
+    #[test]
+    fn edl_timecodes() {
+        let out = ExporterBuilder::default()
+            .format(ExportFormat::Edl)
+            .build()
+            .unwrap()
+            .export(&segments());
+        assert!(out.starts_with("TITLE: Transcript\nFCM: NON-DROP FRAME\n"));
+        assert!(out.contains(
+            "001  AX       V     C        00:00:00:00 00:00:01:13 00:00:00:00 00:00:01:13"
+        ));
+        assert!(out.contains("* LOC: 01:01:00:00 WHITE   ask <not> what"));
+    }
+
+    #[test]
+    fn xml_markers_are_escaped() {
+        for format in [ExportFormat::Fcpxml, ExportFormat::PremiereXml] {
+            let out = ExporterBuilder::default()
+                .format(format)
+                .frame_rate(30u32)
+                .build()
+                .unwrap()
+                .export(&segments());
+            assert!(out.contains("ask &lt;not&gt; what"));
+            assert!(!out.contains("<not>"));
+        }
+    }
+
+    #[test]
+    fn html_player() {
+        let out = ExporterBuilder::default()
+            .format(ExportFormat::Html)
+            .audio("jfk.wav")
+            .build()
+            .unwrap()
+            .export(&segments());
+        assert!(out.contains(r#"<audio id="player" controls preload="metadata" src="jfk.wav">"#));
+        assert_eq!(out.matches("<p>").count(), 2);
+        assert!(out.contains(r#"<span class="speaker">Kennedy</span>"#));
+        assert!(out.contains(r#"data-start="3660.00">01:01:00</a>"#));
+    }
+
+    #[test]
+    fn language_tags() {
+        let exporter = |format, language| {
+            ExporterBuilder::default()
+                .format(format)
+                .language(language)
+                .build()
+                .unwrap()
+                .export(&segments())
+        };
+        assert!(exporter(ExportFormat::Html, Language::Italian).contains("<html lang=\"it\">\n"));
+        assert!(
+            exporter(ExportFormat::Html, Language::Arabic)
+                .contains(r#"<html lang="ar" dir="rtl">"#)
+        );
+        assert!(
+            exporter(ExportFormat::Vtt, Language::Hebrew)
+                .starts_with("WEBVTT\nLanguage: he\n\n00:00:00.000 --> ")
+        );
+    }
+
+    #[test]
+    fn html_player_path() {
+        let out = ExporterBuilder::default()
+            .format(ExportFormat::Html)
+            .audio("talks/take #1?100%.wav")
+            .build()
+            .unwrap()
+            .export(&segments());
+        assert!(out.contains(r#"src="talks/take%20%231%3F100%25.wav""#));
+    }
+
+    #[test]
+    fn markdown_headings() {
+        let out = ExporterBuilder::default()
+            .format(ExportFormat::Markdown)
+            .title("JFK")
+            .build()
+            .unwrap()
+            .export(&segments());
+        assert_eq!(
+            out,
+            "# JFK\n\n## [00:00:00]\n\nAnd so my fellow Americans\n\n## [01:01:00] Kennedy\n\nask <not> what\n"
+        );
+    }
+
+    #[test]
+    fn subtitles() {
+        let srt = ExporterBuilder::default()
+            .format(ExportFormat::Srt)
+            .build()
+            .unwrap()
+            .export(&segments());
+        assert!(
+            srt.starts_with("1\n00:00:00,000 --> 00:00:01,500\nAnd so my fellow Americans\n\n2\n")
+        );
+
+        let vtt = ExporterBuilder::default()
+            .format(ExportFormat::Vtt)
+            .build()
+            .unwrap()
+            .export(&segments());
+        assert!(vtt.starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:01.500\n"));
+        assert!(vtt.contains("01:01:00.000 --> 01:01:02.040\n<v Kennedy>ask &lt;not&gt;\twhat\n"));
+    }
+
+    #[test]
+    fn zero_frame_rate() {
+        let error = ExporterBuilder::default()
+            .frame_rate(0u32)
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, ExporterBuilderError::ValidationError(_)));
+    }
+}
@@ -0,0 +1,438 @@
+use std::{collections::VecDeque, mem::take, time::Duration};
+#[cfg(feature = "tokio")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+#[cfg(feature = "tokio")]
+use tokio_stream::Stream;
+
+use crate::{
+    Error, Event, Language, Segment,
+    align::{TimedWord, is_unspaced, segment_words},
+};
+
+/// Default pause between two sentences starting a new paragraph
+pub const PARAGRAPH_PAUSE: Duration = Duration::from_secs(2);
+
+/// Words ending with a dot that do not close a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr.", "mrs.", "ms.", "dr.", "prof.", "st.", "jr.", "sr.", "vs.", "etc.", "e.g.", "i.e.",
+    "no.", "fig.", "approx.",
+];
+
+/// Consecutive segments spoken by the same speaker without long pauses
+#[derive(Clone, Debug, PartialEq)]
+pub struct Paragraph {
+    pub start_offset: Duration,
+    pub end_offset: Duration,
+    pub speaker: Option<String>,
+    pub segments: Vec<Segment>,
+}
+
+impl Paragraph {
+    /// The paragraph text, segments are joined using the spacing rules of their script
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|s| s.transcription.trim())
+            .fold(String::new(), join)
+    }
+}
+
+/// Incrementally regroups segments into sentences.
+///
+/// Whisper segment boundaries often fall in the middle of a sentence: the grouper
+/// buffers the words until a sentence terminator of the language is found.
+/// Sentence offsets are interpolated from the offsets of the segments they span.
+#[derive(Clone, Debug)]
+pub struct SentenceGrouper {
+    terminators: &'static [char],
+    words: Vec<TimedWord>,
+    speaker: Option<String>,
+}
+
+impl SentenceGrouper {
+    pub fn new(language: Language) -> Self {
+        Self {
+            terminators: terminators(language),
+            words: Vec::new(),
+            speaker: None,
+        }
+    }
+
+    /// Feed a segment returning the sentences completed by it
+    pub fn push(&mut self, segment: Segment) -> Vec<Segment> {
+        let mut sentences = Vec::new();
+        if segment.speaker != self.speaker {
+            sentences.extend(self.finish());
+            self.speaker = segment.speaker.clone();
+        }
+        for word in segment_words(&[segment]) {
+            let closes = self.closes_sentence(&word.text);
+            self.words.push(word);
+            if closes {
+                sentences.extend(self.finish());
+            }
+        }
+        sentences
+    }
+
+    /// Flush the buffered words as a sentence, even if it is not terminated
+    pub fn finish(&mut self) -> Option<Segment> {
+        let words = take(&mut self.words);
+        let (first, last) = (words.first()?, words.last()?);
+        Some(Segment {
+            start_offset: first.start_offset,
+            end_offset: last.end_offset,
+            transcription: words
+                .iter()
+                .fold(String::new(), |text, word| join(text, &word.text)),
+            speaker: self.speaker.clone(),
+        })
+    }
+
+    fn closes_sentence(&self, word: &str) -> bool {
+        let trimmed = word.trim_end_matches(['"', '\'', ')', ']', '»', '”', '’', '」', '』', '）']);
+        if !trimmed.ends_with(self.terminators) {
+            return false;
+        }
+        let lowercase = trimmed.to_lowercase();
+        !ABBREVIATIONS.contains(&lowercase.as_str())
+    }
+}
+
+/// Incrementally groups segments into paragraphs, a new paragraph starts on long
+/// pauses and speaker changes.
+#[derive(Clone, Debug)]
+pub struct ParagraphGrouper {
+    pause: Duration,
+    segments: Vec<Segment>,
+}
+
+impl ParagraphGrouper {
+    pub fn new(pause: Duration) -> Self {
+        Self {
+            pause,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Feed a segment returning the paragraph completed by it
+    pub fn push(&mut self, segment: Segment) -> Option<Paragraph> {
+        let completed = match self.segments.last() {
+            Some(last)
+                if last.speaker != segment.speaker
+                    || segment.start_offset.saturating_sub(last.end_offset) >= self.pause =>
+            {
+                self.finish()
+            }
+            _ => None,
+        };
+        self.segments.push(segment);
+        completed
+    }
+
+    /// Flush the buffered segments as a paragraph
+    pub fn finish(&mut self) -> Option<Paragraph> {
+        let segments = take(&mut self.segments);
+        let (first, last) = (segments.first()?, segments.last()?);
+        Some(Paragraph {
+            start_offset: first.start_offset,
+            end_offset: last.end_offset,
+            speaker: first.speaker.clone(),
+            segments,
+        })
+    }
+}
+
+impl Default for ParagraphGrouper {
+    fn default() -> Self {
+        Self::new(PARAGRAPH_PAUSE)
+    }
+}
+
+/// Regroup a finished transcript into sentences
+pub fn sentences(segments: &[Segment], language: Language) -> Vec<Segment> {
+    let mut grouper = SentenceGrouper::new(language);
+    let mut sentences: Vec<Segment> = segments
+        .iter()
+        .flat_map(|segment| grouper.push(segment.clone()))
+        .collect();
+    sentences.extend(grouper.finish());
+    sentences
+}
+
+/// Regroup a finished transcript into paragraphs of sentences
+pub fn paragraphs(segments: &[Segment], language: Language, pause: Duration) -> Vec<Paragraph> {
+    group_paragraphs(&sentences(segments, language), pause)
+}
+
+/// Group segments into paragraphs without splitting them into sentences
+pub(crate) fn group_paragraphs(segments: &[Segment], pause: Duration) -> Vec<Paragraph> {
+    let mut grouper = ParagraphGrouper::new(pause);
+    let mut paragraphs: Vec<Paragraph> = segments
+        .iter()
+        .filter_map(|segment| grouper.push(segment.clone()))
+        .collect();
+    paragraphs.extend(grouper.finish());
+    paragraphs
+}
+
+/// Stream adapter replacing the [Event::Segment] events of a live transcription
+/// with one [Event::Segment] per sentence. Other events are forwarded untouched.
+///
+/// The events of [crate::Whisper::transcribe_blocking] are grouped the same way by the
+/// [Iterator] implementation.
+pub struct Sentences<S> {
+    inner: S,
+    grouper: SentenceGrouper,
+    pending: VecDeque<Event>,
+    percentage: f32,
+    done: bool,
+}
+
+impl<S> Sentences<S> {
+    pub fn new(inner: S, language: Language) -> Self {
+        Self {
+            inner,
+            grouper: SentenceGrouper::new(language),
+            pending: VecDeque::new(),
+            percentage: 0.,
+            done: false,
+        }
+    }
+
+    fn enqueue(&mut self, sentence: Segment) {
+        self.pending.push_back(Event::Segment {
+            start_offset: sentence.start_offset,
+            end_offset: sentence.end_offset,
+            percentage: self.percentage,
+            transcription: sentence.transcription,
+        });
+    }
+
+    /// Group the next item of the inner source, returning the one to forward right away
+    fn accept(&mut self, item: Option<Result<Event, Error>>) -> Option<Result<Event, Error>> {
+        match item {
+            Some(Ok(event)) => {
+                if let Event::Segment { percentage, .. } = &event {
+                    self.percentage = *percentage;
+                }
+                match Segment::try_from(event) {
+                    Ok(segment) => {
+                        for sentence in self.grouper.push(segment) {
+                            self.enqueue(sentence);
+                        }
+                        None
+                    }
+                    Err(event) => Some(Ok(event)),
+                }
+            }
+            Some(Err(err)) => Some(Err(err)),
+            None => {
+                self.done = true;
+                if let Some(sentence) = self.grouper.finish() {
+                    self.enqueue(sentence);
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S> Stream for Sentences<S>
+where
+    S: Stream<Item = Result<Event, Error>> + Unpin,
+{
+    type Item = Result<Event, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let item = ready!(Pin::new(&mut this.inner).poll_next(cx));
+            if let Some(item) = this.accept(item) {
+                return Poll::Ready(Some(item));
+            }
+        }
+    }
+}
+
+impl<I> Iterator for Sentences<I>
+where
+    I: Iterator<Item = Result<Event, Error>>,
+{
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            let item = self.inner.next();
+            if let Some(item) = self.accept(item) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+/// Append a word, scripts written without spaces are joined directly
+fn join(mut text: String, word: &str) -> String {
+    let unspaced = text.chars().last().is_some_and(is_unspaced)
+        || word.chars().next().is_some_and(is_unspaced)
+        || word.starts_with(['。', '、', '，', '！', '？', '：', '；']);
+    if !text.is_empty() && !unspaced {
+        text.push(' ');
+    }
+    text.push_str(word);
+    text
+}
+
+/// Sentence terminators used by the language
+fn terminators(language: Language) -> &'static [char] {
+    match language {
+        Language::Chinese | Language::Japanese => &['.', '!', '?', '…', '。', '！', '？', '．'],
+        Language::Hindi
+        | Language::Marathi
+        | Language::Nepali
+        | Language::Sanskrit
+        | Language::Bengali
+        | Language::Punjabi
+        | Language::Assamese => &['.', '!', '?', '…', '।', '॥'],
+        Language::Arabic
+        | Language::Persian
+        | Language::Urdu
+        | Language::Pashto
+        | Language::Sindhi => &['.', '!', '?', '…', '؟', '۔'],
+        Language::Greek => &['.', '!', ';', '…'],
+        Language::Armenian => &['.', '!', '?', '…', '։'],
+        Language::Amharic => &['.', '!', '?', '…', '።'],
+        Language::Myanmar => &['.', '!', '?', '…', '။'],
+        Language::Tibetan => &['.', '!', '?', '…', '།'],
+        Language::Khmer => &['.', '!', '?', '…', '។'],
+        _ => &['.', '!', '?', '…'],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "tokio")]
+    use tokio_stream::{StreamExt, iter};
+
+    use super::*;
+
+    fn segment(start: u64, end: u64, text: &str) -> Segment {
+        Segment {
+            start_offset: Duration::from_millis(start),
+            end_offset: Duration::from_millis(end),
+            transcription: text.to_owned(),
+            speaker: None,
+        }
+    }
+
+    #[test]
+    fn sentences_across_segments() {
+        let sentences = sentences(
+            &[
+                segment(0, 2000, " Mr. Smith went to Washington. He"),
+                segment(2000, 4000, " stayed there! Then"),
+            ],
+            Language::English,
+        );
+        let texts: Vec<&str> = sentences.iter().map(|s| s.transcription.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["Mr. Smith went to Washington.", "He stayed there!", "Then"]
+        );
+        assert_eq!(sentences[0].start_offset, Duration::ZERO);
+        assert!(sentences[1].start_offset < Duration::from_millis(2000));
+        assert_eq!(sentences[1].end_offset, sentences[2].start_offset);
+        assert_eq!(sentences[2].end_offset, Duration::from_millis(4000));
+    }
+
+    #[test]
+    fn cjk_sentences() {
+        let sentences = sentences(
+            &[
+                segment(0, 1000, "今日は晴れです。明日"),
+                segment(1000, 2000, "は雨です。"),
+            ],
+            Language::Japanese,
+        );
+        let texts: Vec<&str> = sentences.iter().map(|s| s.transcription.as_str()).collect();
+        assert_eq!(texts, vec!["今日は晴れです。", "明日は雨です。"]);
+    }
+
+    #[test]
+    fn paragraphs_on_pause_and_speaker() {
+        let mut speaker = segment(9000, 10000, " Thank you.");
+        speaker.speaker = Some("Audience".to_owned());
+        let paragraphs = paragraphs(
+            &[
+                segment(0, 1000, " First."),
+                segment(1500, 2500, " Second."),
+                segment(6000, 7000, " Third."),
+                speaker,
+            ],
+            Language::English,
+            PARAGRAPH_PAUSE,
+        );
+        assert_eq!(paragraphs.len(), 3);
+        assert_eq!(paragraphs[0].text(), "First. Second.");
+        assert_eq!(paragraphs[0].end_offset, Duration::from_millis(2500));
+        assert_eq!(paragraphs[2].speaker.as_deref(), Some("Audience"));
+    }
+
+    fn live_events() -> Vec<Result<Event, Error>> {
+        vec![
+            Ok(Event::DownloadCompleted {
+                file: "model".to_owned(),
+            }),
+            Ok(Event::Segment {
+                start_offset: Duration::ZERO,
+                end_offset: Duration::from_secs(1),
+                percentage: 0.5,
+                transcription: " Ask not what".to_owned(),
+            }),
+            Ok(Event::Segment {
+                start_offset: Duration::from_secs(1),
+                end_offset: Duration::from_secs(2),
+                percentage: 1.,
+                transcription: " your country can do.".to_owned(),
+            }),
+        ]
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn live_sentences() {
+        let events: Vec<Event> = Sentences::new(iter(live_events()), Language::English)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(events.len(), 2);
+        assert!(events[0].is_download_completed());
+        assert_eq!(events[1].to_string(), "Ask not what your country can do.");
+    }
+
+    #[test]
+    fn blocking_sentences() {
+        let events: Vec<Event> = Sentences::new(live_events().into_iter(), Language::English)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].to_string(), "Ask not what your country can do.");
+    }
+}
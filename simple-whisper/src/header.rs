@@ -0,0 +1,242 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::{ModelFamily, Quantization};
+
+/// Magic number opening every ggml model file
+const GGML_MAGIC: u32 = 0x67676d6c;
+
+/// Vocabulary size of the multilingual whisper models, English only models have a smaller one
+const MULTILINGUAL_VOCAB: i32 = 51865;
+
+/// Multiplier of the quantization version stored in the `ftype` field
+const QNT_VERSION_FACTOR: i32 = 1000;
+
+/// Bytes of an f16 value, the type of the KV caches
+const F16_SIZE: u64 = 2;
+
+/// Bytes of an f32 value, the type of the activations
+const F32_SIZE: u64 = 4;
+
+/// Activations kept by the encoder graph for each audio state
+const ENCODER_ACTIVATIONS: u64 = 8;
+
+/// Hyperparameters stored at the beginning of a ggml whisper model file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelHeader {
+    pub n_vocab: i32,
+    pub n_audio_ctx: i32,
+    pub n_audio_state: i32,
+    pub n_audio_head: i32,
+    pub n_audio_layer: i32,
+    pub n_text_ctx: i32,
+    pub n_text_state: i32,
+    pub n_text_head: i32,
+    pub n_text_layer: i32,
+    pub n_mels: i32,
+    /// Weight type, the quantization version is stored in the thousands
+    pub ftype: i32,
+}
+
+impl ModelHeader {
+    /// Read and validate the header without loading the weights
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {reason}", path.display()),
+            )
+        };
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        if u32::from_le_bytes(buf) != GGML_MAGIC {
+            return Err(invalid("not a ggml model file".to_owned()));
+        }
+        let mut fields = [0; 11];
+        for field in &mut fields {
+            reader.read_exact(&mut buf)?;
+            *field = i32::from_le_bytes(buf);
+        }
+        let [
+            n_vocab,
+            n_audio_ctx,
+            n_audio_state,
+            n_audio_head,
+            n_audio_layer,
+            n_text_ctx,
+            n_text_state,
+            n_text_head,
+            n_text_layer,
+            n_mels,
+            ftype,
+        ] = fields;
+        let header = Self {
+            n_vocab,
+            n_audio_ctx,
+            n_audio_state,
+            n_audio_head,
+            n_audio_layer,
+            n_text_ctx,
+            n_text_state,
+            n_text_head,
+            n_text_layer,
+            n_mels,
+            ftype,
+        };
+        if fields[..10].iter().any(|field| *field <= 0) || ftype < 0 {
+            return Err(invalid(format!("corrupted header {header:?}")));
+        }
+        if !matches!(n_mels, 80 | 128) {
+            return Err(invalid(format!("unsupported number of mel bins {n_mels}")));
+        }
+        Ok(header)
+    }
+
+    /// True if the vocabulary covers multiple languages, false if it is English only
+    pub fn is_multilingual(&self) -> bool {
+        self.n_vocab >= MULTILINGUAL_VOCAB
+    }
+
+    /// Name of the weight type, e.g. `f16` or `q5_0`
+    pub fn weight_type(&self) -> &'static str {
+        match self.ftype % QNT_VERSION_FACTOR {
+            0 => "f32",
+            1 => "f16",
+            2 => "q4_0",
+            3 => "q4_1",
+            4 => "q4_1_some_f16",
+            7 => "q8_0",
+            8 => "q5_0",
+            9 => "q5_1",
+            10 => "q2_k",
+            11 => "q3_k",
+            12 => "q4_k",
+            13 => "q5_k",
+            14 => "q6_k",
+            _ => "unknown",
+        }
+    }
+
+    /// The weight type, if used by the built-in models
+    pub fn quantization(&self) -> Option<Quantization> {
+        match self.ftype % QNT_VERSION_FACTOR {
+            1 => Some(Quantization::F16),
+            7 => Some(Quantization::Q8_0),
+            8 => Some(Quantization::Q5_0),
+            9 => Some(Quantization::Q5_1),
+            _ => None,
+        }
+    }
+
+    /// Estimated memory in bytes of a whisper.cpp state running on `threads` threads,
+    /// on top of the weights: KV caches, encoder/decoder compute buffers and per thread scratch
+    pub fn state_memory(&self, threads: usize) -> u64 {
+        let [
+            n_vocab,
+            n_audio_ctx,
+            n_audio_state,
+            n_audio_head,
+            n_text_ctx,
+            n_text_state,
+            n_text_layer,
+        ] = [
+            self.n_vocab,
+            self.n_audio_ctx,
+            self.n_audio_state,
+            self.n_audio_head,
+            self.n_text_ctx,
+            self.n_text_state,
+            self.n_text_layer,
+        ]
+        .map(|field| field.max(0) as u64);
+        // Keys and values of the decoder self attention and of the cross attention
+        let kv_cache = 2 * F16_SIZE * n_text_layer * (n_text_ctx + n_audio_ctx) * n_text_state;
+        // Encoder activations and attention scores, decoder logits
+        let encoder = F32_SIZE
+            * n_audio_ctx
+            * (ENCODER_ACTIVATIONS * n_audio_state + n_audio_head * n_audio_ctx);
+        let decoder = F32_SIZE * n_vocab * n_text_ctx;
+        let scratch = F32_SIZE * n_audio_ctx * n_audio_state * threads.max(1) as u64;
+        kv_cache + encoder + decoder + scratch
+    }
+
+    /// The architecture matching the layers of the model.
+    ///
+    /// None for unknown architectures and for large models with 80 mel bins,
+    /// since V1 and V2 share the same shape.
+    pub fn family(&self) -> Option<ModelFamily> {
+        match (self.n_audio_layer, self.n_text_layer, self.n_mels) {
+            (4, 4, 80) => Some(ModelFamily::Tiny),
+            (6, 6, 80) => Some(ModelFamily::Base),
+            (12, 12, 80) => Some(ModelFamily::Small),
+            (24, 24, 80) => Some(ModelFamily::Medium),
+            (32, 32, 128) => Some(ModelFamily::LargeV3),
+            (32, 4, 128) => Some(ModelFamily::LargeV3Turbo),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    /// A ggml file containing only the header of the tiny model
+    pub(crate) fn ggml_file(n_vocab: i32, ftype: i32) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&GGML_MAGIC.to_le_bytes()).unwrap();
+        for field in [n_vocab, 1500, 384, 6, 4, 448, 384, 6, 4, 80, ftype] {
+            file.write_all(&field.to_le_bytes()).unwrap();
+        }
+        file
+    }
+
+    #[test]
+    fn read_header() {
+        let header = ModelHeader::read(ggml_file(51865, 1).path()).unwrap();
+        assert!(header.is_multilingual());
+        assert_eq!(header.n_audio_ctx, 1500);
+        assert_eq!(header.n_mels, 80);
+        assert_eq!(header.family(), Some(ModelFamily::Tiny));
+        assert_eq!(header.quantization(), Some(Quantization::F16));
+
+        let header = ModelHeader::read(ggml_file(51864, 2009).path()).unwrap();
+        assert!(!header.is_multilingual());
+        assert_eq!(header.weight_type(), "q5_1");
+        assert_eq!(header.quantization(), Some(Quantization::Q5_1));
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        let mut invalid = NamedTempFile::new().unwrap();
+        invalid.write_all(b"not a model").unwrap();
+        assert!(ModelHeader::read(invalid.path()).is_err());
+
+        let mut truncated = NamedTempFile::new().unwrap();
+        truncated.write_all(&GGML_MAGIC.to_le_bytes()).unwrap();
+        truncated.write_all(&51865i32.to_le_bytes()).unwrap();
+        assert!(ModelHeader::read(truncated.path()).is_err());
+
+        assert!(ModelHeader::read(ggml_file(0, 1).path()).is_err());
+    }
+
+    #[test]
+    fn estimate_state_memory() {
+        let header = ModelHeader::read(ggml_file(51865, 1).path()).unwrap();
+        let single = header.state_memory(1);
+        assert!((150_000_000..250_000_000).contains(&single));
+        assert_eq!(
+            header.state_memory(4) - single,
+            3 * header.state_memory(2) - 3 * single
+        );
+        assert_eq!(header.state_memory(0), single);
+    }
+}
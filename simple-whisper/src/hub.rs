@@ -0,0 +1,1096 @@
+use std::{
+    collections::HashMap,
+    fs::{TryLockError, create_dir_all},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+use hf_hub::{Repo, api::tokio::ApiError};
+use reqwest::{
+    Client, Proxy, StatusCode,
+    header::{AUTHORIZATION, CONTENT_RANGE, HeaderMap, HeaderName, HeaderValue, LOCATION, RANGE},
+    redirect::Policy,
+};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::watch,
+    task::spawn_blocking,
+    time::sleep,
+};
+
+use crate::{
+    DownloadConfig, DownloadObserver, Error, Event,
+    download::{
+        expected_path, hex, lfs_sha256, link_blob, published_sha256, remove_cached, update_hasher,
+        verify_cached_file,
+    },
+};
+
+/// Extension of the file receiving the data while downloading
+const PART_EXTENSION: &str = "part";
+
+/// Extension of the file locking a blob while it is downloaded, stored in `.locks`
+/// like the Hugging Face libraries do
+const LOCK_EXTENSION: &str = "lock";
+
+/// Interval between two attempts to acquire the lock of a blob
+const LOCK_POLLING: Duration = Duration::from_millis(100);
+
+/// Upper bound of the wait between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+impl DownloadConfig {
+    fn client(&self, redirect: Policy) -> Result<Client, ApiError> {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &self.token {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {token}"))?,
+            );
+        }
+        let mut builder = Client::builder()
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .default_headers(headers)
+            .redirect(redirect);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        Ok(builder.build()?)
+    }
+
+    fn url(&self, repo: &Repo, file: &str) -> String {
+        format!(
+            "{}/{}/resolve/{}/{file}",
+            self.endpoint.trim_end_matches('/'),
+            repo.url(),
+            repo.url_revision()
+        )
+    }
+}
+
+/// Downloads running in this process, keyed by their location in the cache
+static IN_FLIGHT: LazyLock<Mutex<HashMap<PathBuf, Arc<InFlight>>>> =
+    LazyLock::new(Default::default);
+
+/// A download shared by all the callers requesting the same file
+struct InFlight {
+    state: Mutex<FlightState>,
+    done: watch::Sender<bool>,
+}
+
+/// Observers waiting on the download and the progress to replay to the late ones
+#[derive(Default)]
+struct FlightState {
+    followers: Vec<Arc<dyn DownloadObserver>>,
+    transfer: Option<(String, u64, u64)>,
+}
+
+/// The caller performing the download. Once dropped the followers are woken up
+struct Leader {
+    key: PathBuf,
+    flight: Arc<InFlight>,
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().remove(&self.key);
+        self.flight.done.send_replace(true);
+    }
+}
+
+enum Role {
+    Leader(Leader),
+    Follower(watch::Receiver<bool>),
+}
+
+/// Become the leader of the download or follow the one already running
+fn join(key: PathBuf, observer: &Arc<dyn DownloadObserver>) -> Role {
+    let mut in_flight = IN_FLIGHT.lock().unwrap();
+    if let Some(flight) = in_flight.get(&key) {
+        let mut state = flight.state.lock().unwrap();
+        if let Some((file, size, received)) = &state.transfer {
+            observer.init(file, *size);
+            observer.update(file, *received);
+        }
+        state.followers.push(observer.clone());
+        return Role::Follower(flight.done.subscribe());
+    }
+    let flight = Arc::new(InFlight {
+        state: Default::default(),
+        done: watch::channel(false).0,
+    });
+    in_flight.insert(key.clone(), flight.clone());
+    Role::Leader(Leader { key, flight })
+}
+
+/// Observers of a download: the caller and the ones waiting on it
+struct Listeners {
+    caller: Arc<dyn DownloadObserver>,
+    flight: Arc<InFlight>,
+}
+
+impl Listeners {
+    fn each(&self, notify: impl Fn(&dyn DownloadObserver)) {
+        notify(self.caller.as_ref());
+        let state = self.flight.state.lock().unwrap();
+        state
+            .followers
+            .iter()
+            .for_each(|observer| notify(observer.as_ref()));
+    }
+}
+
+impl DownloadObserver for Listeners {
+    fn init(&self, file: &str, size: u64) {
+        self.flight.state.lock().unwrap().transfer = Some((file.to_owned(), size, 0));
+        self.each(|observer| observer.init(file, size));
+    }
+
+    fn update(&self, file: &str, delta: u64) {
+        if let Some((_, _, received)) = &mut self.flight.state.lock().unwrap().transfer {
+            *received += delta;
+        }
+        self.each(|observer| observer.update(file, delta));
+    }
+
+    fn finish(&self, file: &str) {
+        self.flight.state.lock().unwrap().transfer = None;
+        self.each(|observer| observer.finish(file));
+    }
+
+    fn error(&self, file: &str, error: &Error) {
+        self.flight.state.lock().unwrap().transfer = None;
+        self.each(|observer| observer.error(file, error));
+    }
+
+    fn event(&self, event: &Event) {
+        self.each(|observer| observer.event(event));
+    }
+}
+
+/// Make the file available in the cache.
+///
+/// Concurrent requests of the same file within the process wait for a single download
+/// and receive its events, other processes are kept out by a lock file next to the blob.
+pub async fn download_file(
+    file: &str,
+    force_download: bool,
+    offline: bool,
+    observer: Arc<dyn DownloadObserver>,
+    repo: Repo,
+    config: &DownloadConfig,
+) -> Result<PathBuf, Error> {
+    let downloaded = obtain(file, force_download, offline, &observer, repo, config).await;
+    if let Err(err) = &downloaded {
+        observer.error(file, err);
+    }
+    downloaded
+}
+
+async fn obtain(
+    file: &str,
+    mut force_download: bool,
+    offline: bool,
+    observer: &Arc<dyn DownloadObserver>,
+    repo: Repo,
+    config: &DownloadConfig,
+) -> Result<PathBuf, Error> {
+    let cache = config.cache();
+    let repo = config.repo(repo);
+    loop {
+        let mut in_cache = cache.repo(repo.clone()).get(file);
+        if force_download && !offline {
+            in_cache = None
+        }
+        if let Some(val) = in_cache {
+            if !config.verify_cache {
+                return Ok(val);
+            }
+            match verify_cached(file, &repo, &val, observer.as_ref()).await {
+                Err(Error::ChecksumMismatch { .. }) if config.redownload_corrupted && !offline => {
+                    remove_cached(&val)?
+                }
+                verified => return verified.map(|_| val),
+            }
+        } else if offline {
+            return Err(Error::ModelNotAvailableOffline {
+                file: file.to_owned(),
+                path: expected_path(&cache, &repo, file),
+            });
+        }
+
+        let key = config
+            .cache_dir
+            .join(repo.folder_name())
+            .join(repo.revision())
+            .join(file);
+        let leader = match join(key, observer) {
+            Role::Leader(leader) => leader,
+            Role::Follower(mut done) => {
+                let _ = done.wait_for(|done| *done).await;
+                // The file has just been downloaded, or the leader failed and the download starts over
+                force_download = false;
+                continue;
+            }
+        };
+        let listeners = Listeners {
+            caller: observer.clone(),
+            flight: leader.flight.clone(),
+        };
+        return match fetch(file, &repo, config, &listeners).await {
+            Err(Error::ChecksumMismatch { .. }) if config.redownload_corrupted => {
+                fetch(file, &repo, config, &listeners).await
+            }
+            fetched => fetched,
+        };
+    }
+}
+
+/// Remote file properties advertised by the hub
+#[derive(Debug)]
+struct Metadata {
+    commit_hash: String,
+    etag: String,
+    size: usize,
+    url: String,
+}
+
+/// Retrieve commit, etag and size of the file.
+///
+/// Only relative redirects are followed, the absolute ones point to the storage
+/// hosting the content which lacks the hub headers.
+async fn metadata(config: &DownloadConfig, url: &str) -> Result<Metadata, ApiError> {
+    let relative = Policy::custom(|attempt| {
+        if attempt.previous().len() > 10 {
+            attempt.error("too many redirects")
+        } else if attempt
+            .previous()
+            .last()
+            .is_some_and(|last| last.make_relative(attempt.url()).is_none())
+        {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    });
+    let response = config
+        .client(relative)?
+        .get(url)
+        .header(RANGE, "bytes=0-0")
+        .send()
+        .await?
+        .error_for_status()?;
+    let headers = response.headers();
+    let header_commit = HeaderName::from_static("x-repo-commit");
+    let header_linked_etag = HeaderName::from_static("x-linked-etag");
+    let header_etag = HeaderName::from_static("etag");
+
+    let etag = match headers.get(&header_linked_etag) {
+        Some(etag) => etag,
+        None => headers
+            .get(&header_etag)
+            .ok_or(ApiError::MissingHeader(header_etag))?,
+    };
+    let etag = etag.to_str()?.replace('"', "");
+    let commit_hash = headers
+        .get(&header_commit)
+        .ok_or(ApiError::MissingHeader(header_commit))?
+        .to_str()?
+        .to_owned();
+
+    // The content is usually hosted elsewhere, that location knows the size
+    let (response, url) = if response.status().is_redirection() {
+        let location = headers
+            .get(LOCATION)
+            .ok_or(ApiError::MissingHeader(LOCATION))?
+            .to_str()?;
+        let location = response
+            .url()
+            .join(location)
+            .map_err(|_| ApiError::InvalidHeader(LOCATION))?;
+        let response = config
+            .client(Policy::default())?
+            .get(location.clone())
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .await?
+            .error_for_status()?;
+        (response, location.to_string())
+    } else {
+        (response, url.to_owned())
+    };
+    let size = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .ok_or(ApiError::MissingHeader(CONTENT_RANGE))?
+        .to_str()?
+        .rsplit('/')
+        .next()
+        .ok_or(ApiError::InvalidHeader(CONTENT_RANGE))?
+        .parse()?;
+
+    Ok(Metadata {
+        commit_hash,
+        etag,
+        size,
+        url,
+    })
+}
+
+/// Download the file into the cache, using the same layout of the Hugging Face libraries:
+/// the content is stored in `blobs/<etag>` and linked from `snapshots/<commit>/<file>`
+async fn fetch(
+    file: &str,
+    repo: &Repo,
+    config: &DownloadConfig,
+    listeners: &Listeners,
+) -> Result<PathBuf, Error> {
+    let metadata = metadata(config, &config.url(repo, file)).await?;
+    let repo_path = config.cache_dir.join(repo.folder_name());
+    let blob_path = repo_path.join("blobs").join(&metadata.etag);
+    create_dir_all(repo_path.join("blobs"))?;
+
+    let lock = lock_blob(&repo_path, &metadata.etag).await?;
+    // Another process may have completed the download while waiting for the lock
+    if !blob_path.exists() {
+        let part_path = blob_path.with_extension(PART_EXTENSION);
+        let found = transfer(file, &metadata, &part_path, config, listeners).await?;
+        let expected = published_sha256(repo, file).or(lfs_sha256(&metadata.etag));
+        if let Some(expected) = expected {
+            listeners.event(&Event::Verifying {
+                file: file.to_owned(),
+            });
+            if expected != found {
+                std::fs::remove_file(&part_path)?;
+                return Err(Error::ChecksumMismatch {
+                    file: file.to_owned(),
+                    expected: expected.to_owned(),
+                    found,
+                });
+            }
+        }
+        tokio::fs::rename(&part_path, &blob_path).await?;
+    }
+
+    let pointer_path = repo_path
+        .join("snapshots")
+        .join(&metadata.commit_hash)
+        .join(file);
+    create_dir_all(pointer_path.parent().unwrap())?;
+    link_blob(&blob_path, &pointer_path, file)?;
+    config
+        .cache()
+        .repo(repo.clone())
+        .create_ref(&metadata.commit_hash)?;
+    drop(lock);
+
+    Ok(pointer_path)
+}
+
+/// Wait for the exclusive lock of the blob, shared with the other processes using the cache
+async fn lock_blob(repo_path: &Path, etag: &str) -> io::Result<std::fs::File> {
+    let locks = repo_path.join(".locks");
+    create_dir_all(&locks)?;
+    let lock = std::fs::File::create(locks.join(format!("{etag}.{LOCK_EXTENSION}")))?;
+    loop {
+        match lock.try_lock() {
+            Ok(()) => return Ok(lock),
+            Err(TryLockError::WouldBlock) => sleep(LOCK_POLLING).await,
+            Err(TryLockError::Error(err)) => return Err(err),
+        }
+    }
+}
+
+/// Stream the content into the part file, returning its SHA-256.
+///
+/// An existing part file is resumed with a range request, the same happens when the
+/// connection drops until the retries are exhausted.
+async fn transfer(
+    file: &str,
+    metadata: &Metadata,
+    part_path: &Path,
+    config: &DownloadConfig,
+    observer: &dyn DownloadObserver,
+) -> Result<String, ApiError> {
+    let client = config.client(Policy::default())?;
+    observer.init(file, metadata.size as u64);
+    let (mut offset, mut hasher) = resume(part_path, metadata.size).await?;
+    observer.update(file, offset as u64);
+
+    let mut attempt = 0;
+    while let Err(err) = transfer_range(
+        &client,
+        file,
+        metadata,
+        part_path,
+        &mut offset,
+        &mut hasher,
+        observer,
+    )
+    .await
+    {
+        if attempt == config.retries {
+            return Err(match attempt {
+                0 => err,
+                _ => ApiError::TooManyRetries(err.into()),
+            });
+        }
+        attempt += 1;
+        let delay = config
+            .retry_backoff
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(MAX_BACKOFF);
+        observer.event(&Event::DownloadRetry {
+            file: file.to_owned(),
+            attempt,
+            delay,
+            reason: err.to_string(),
+        });
+        sleep(delay).await;
+    }
+    observer.finish(file);
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Length and hash of the data already in the part file
+async fn resume(part_path: &Path, size: usize) -> Result<(usize, Sha256), ApiError> {
+    let part_path = part_path.to_owned();
+    let resumed = spawn_blocking(move || -> io::Result<(usize, Sha256)> {
+        let mut hasher = Sha256::new();
+        let len = match std::fs::metadata(&part_path) {
+            Ok(metadata) if metadata.len() as usize <= size => metadata.len() as usize,
+            Ok(_) => {
+                std::fs::remove_file(&part_path)?;
+                0
+            }
+            Err(_) => 0,
+        };
+        if len > 0 {
+            update_hasher(&mut hasher, std::fs::File::open(&part_path)?)?;
+        }
+        Ok((len, hasher))
+    })
+    .await?;
+    Ok(resumed?)
+}
+
+/// Append the data from `offset` to the end of the file
+async fn transfer_range(
+    client: &Client,
+    file: &str,
+    metadata: &Metadata,
+    part_path: &Path,
+    offset: &mut usize,
+    hasher: &mut Sha256,
+    observer: &dyn DownloadObserver,
+) -> Result<(), ApiError> {
+    // A complete part file left behind before its rename, nothing is missing
+    if *offset == metadata.size {
+        return Ok(());
+    }
+    let mut part = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(part_path)
+        .await?;
+    let mut response = request_range(client, &metadata.url, *offset).await?;
+    if *offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The part file does not match the remote file, start over
+        restart(&mut part, file, metadata, offset, hasher, observer).await?;
+        response = request_range(client, &metadata.url, 0).await?;
+    }
+    let mut response = response.error_for_status()?;
+    if *offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+        // Range not supported, start over
+        restart(&mut part, file, metadata, offset, hasher, observer).await?;
+    }
+    while let Some(chunk) = response.chunk().await? {
+        hasher.update(&chunk);
+        part.write_all(&chunk).await?;
+        *offset += chunk.len();
+        observer.update(file, chunk.len() as u64);
+    }
+    part.flush().await?;
+    if *offset < metadata.size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("received {offset} of {} bytes", metadata.size),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Request the content from `offset`, the whole file from the start
+async fn request_range(
+    client: &Client,
+    url: &str,
+    offset: usize,
+) -> Result<reqwest::Response, ApiError> {
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    Ok(request.send().await?)
+}
+
+/// Discard the data received so far, the progress of the observer starts over too
+async fn restart(
+    part: &mut File,
+    file: &str,
+    metadata: &Metadata,
+    offset: &mut usize,
+    hasher: &mut Sha256,
+    observer: &dyn DownloadObserver,
+) -> io::Result<()> {
+    part.set_len(0).await?;
+    *offset = 0;
+    *hasher = Sha256::new();
+    observer.init(file, metadata.size as u64);
+    Ok(())
+}
+
+/// Check a cached file on the blocking thread pool
+async fn verify_cached(
+    file: &str,
+    repo: &Repo,
+    path: &Path,
+    observer: &dyn DownloadObserver,
+) -> Result<(), Error> {
+    observer.event(&Event::Verifying {
+        file: file.to_owned(),
+    });
+    let (file, repo, path) = (file.to_owned(), repo.clone(), path.to_owned());
+    spawn_blocking(move || verify_cached_file(&file, &repo, &path))
+        .await
+        .map_err(io::Error::from)?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{canonicalize, create_dir_all, read_to_string, write},
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use hf_hub::{Cache, RepoType};
+    use tempfile::tempdir;
+    use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+    use super::*;
+    use crate::{
+        ChannelObserver, DownloadConfigBuilder, DownloadConfigBuilderError, ProgressBarObserver,
+    };
+
+    const COMMIT: &str = "0123456789abcdef";
+    const ETAG: &str = "feedbeef";
+    const CONTENT: &[u8] = b"ggml model weights";
+
+    /// Minimal stand-in for the Hugging Face hub serving the same file for any path
+    struct Hub {
+        address: String,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Hub {
+        fn serve() -> Hub {
+            Self::serve_file(CONTENT.to_vec(), ETAG.to_owned(), 0)
+        }
+
+        /// The first `drops` transfers of the content are interrupted half-way
+        fn serve_file(content: Vec<u8>, etag: String, mut drops: usize) -> Hub {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let log = requests.clone();
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut request = String::new();
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                        request.push_str(&line.to_ascii_lowercase());
+                        line.clear();
+                    }
+                    let range = request
+                        .lines()
+                        .find_map(|line| line.strip_prefix("range: bytes="))
+                        .and_then(|range| range.split_once('-'))
+                        .map(|(start, end)| (start.parse::<usize>().unwrap(), end.to_owned()));
+                    let (status, mut headers, body) = match range {
+                        Some((0, end)) if end == "0" => (
+                            "206 Partial Content",
+                            format!("x-repo-commit: {COMMIT}\r\nx-linked-etag: \"{etag}\"\r\n"),
+                            &content[..1],
+                        ),
+                        Some((start, _)) => {
+                            ("206 Partial Content", String::new(), &content[start..])
+                        }
+                        None => ("200 OK", String::new(), &content[..]),
+                    };
+                    if status.starts_with("206") {
+                        let start = content.len() - body.len();
+                        headers.push_str(&format!(
+                            "content-range: bytes {start}-{}/{}\r\n",
+                            start + body.len() - 1,
+                            content.len()
+                        ));
+                    }
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {status}\r\n{headers}content-length: {}\r\nconnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    let body = if body.len() > 1 && drops > 0 {
+                        drops -= 1;
+                        &body[..body.len() / 2]
+                    } else {
+                        body
+                    };
+                    let _ = stream.write_all(body);
+                    log.lock().unwrap().push(request);
+                }
+            });
+            Hub { address, requests }
+        }
+
+        fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    fn expected_cache_path() {
+        let dir = tempdir().unwrap();
+        let cache = Cache::new(dir.path().into());
+        let repo = Repo::with_revision("owner/model".into(), RepoType::Model, "main".into());
+        let repo_path = dir.path().join("models--owner--model");
+        assert_eq!(
+            expected_path(&cache, &repo, "ggml.bin"),
+            repo_path.join("snapshots").join("main").join("ggml.bin")
+        );
+
+        create_dir_all(repo_path.join("refs")).unwrap();
+        write(repo_path.join("refs").join("main"), "abc123\n").unwrap();
+        assert_eq!(
+            expected_path(&cache, &repo, "ggml.bin"),
+            repo_path.join("snapshots").join("abc123").join("ggml.bin")
+        );
+    }
+
+    #[tokio::test]
+    async fn offline_cache_miss() {
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .cache_dir(dir.path())
+            .build()
+            .unwrap();
+        let repo = Repo::model("owner/model".into());
+        let error = download_file(
+            "ggml.bin",
+            true,
+            true,
+            Arc::new(ProgressBarObserver::default()),
+            repo,
+            &config,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ModelNotAvailableOffline { file, path }
+                if file == "ggml.bin" && path.starts_with(dir.path())
+        ));
+    }
+
+    #[tokio::test]
+    async fn custom_hub() {
+        let hub = Hub::serve();
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .token("secret")
+            .revision("v1.0")
+            .build()
+            .unwrap();
+        let (tx, mut rx) = unbounded_channel();
+
+        let path = download_file(
+            "ggml.bin",
+            false,
+            false,
+            Arc::new(ChannelObserver::new(tx.clone())),
+            Repo::model("owner/model".into()),
+            &config,
+        )
+        .await
+        .unwrap();
+
+        let repo_path = dir.path().join("models--owner--model");
+        assert_eq!(
+            path,
+            repo_path.join("snapshots").join(COMMIT).join("ggml.bin")
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        assert_eq!(
+            read_to_string(repo_path.join("refs").join("v1.0")).unwrap(),
+            COMMIT
+        );
+        let requests = hub.requests();
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            assert!(request.starts_with("get /owner/model/resolve/v1.0/ggml.bin "));
+            assert!(request.contains("authorization: bearer secret"));
+        }
+        assert!(rx.recv().await.unwrap().is_download_started());
+
+        // The second request is served by the cache
+        download_file(
+            "ggml.bin",
+            false,
+            false,
+            Arc::new(ChannelObserver::new(tx)),
+            Repo::model("owner/model".into()),
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(hub.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn through_proxy() {
+        let proxy = Hub::serve();
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint("http://hub.invalid")
+            .cache_dir(dir.path())
+            .proxy(proxy.address.clone())
+            .build()
+            .unwrap();
+
+        let path = download_file(
+            "ggml.bin",
+            false,
+            false,
+            Arc::new(ChannelObserver::new(unbounded_channel().0)),
+            Repo::model("owner/model".into()),
+            &config,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read(path).unwrap(), CONTENT);
+        assert!(
+            proxy.requests()[0]
+                .starts_with("get http://hub.invalid/owner/model/resolve/main/ggml.bin ")
+        );
+    }
+
+    #[test]
+    fn invalid_proxy() {
+        let error = DownloadConfigBuilder::default()
+            .proxy("http://[invalid")
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            DownloadConfigBuilderError::ValidationError(_)
+        ));
+    }
+
+    fn download(
+        config: &DownloadConfig,
+        tx: UnboundedSender<Event>,
+    ) -> impl Future<Output = Result<PathBuf, Error>> {
+        download_file(
+            "ggml.bin",
+            false,
+            false,
+            Arc::new(ChannelObserver::new(tx)),
+            Repo::model("owner/model".into()),
+            config,
+        )
+    }
+
+    #[tokio::test]
+    async fn verified_download() {
+        let sha256 = hex(&Sha256::digest(CONTENT));
+        let hub = Hub::serve_file(CONTENT.to_vec(), sha256.clone(), 0);
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .build()
+            .unwrap();
+        let (tx, mut rx) = unbounded_channel();
+
+        let path = download(&config, tx).await.unwrap();
+        assert_eq!(
+            canonicalize(path).unwrap().file_name().unwrap(),
+            sha256.as_str()
+        );
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        assert!(events.last().unwrap().is_verifying());
+    }
+
+    #[tokio::test]
+    async fn corrupted_download() {
+        let hub = Hub::serve_file(
+            CONTENT.to_vec(),
+            hex(&Sha256::digest(b"original weights")),
+            0,
+        );
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .redownload_corrupted(true)
+            .build()
+            .unwrap();
+
+        let error = download(&config, unbounded_channel().0).await.unwrap_err();
+        assert!(matches!(error, Error::ChecksumMismatch { file, .. } if file == "ggml.bin"));
+        // The corrupted file was downloaded twice and never stored
+        assert_eq!(hub.requests().len(), 4);
+        let repo_path = dir.path().join("models--owner--model");
+        assert_eq!(
+            std::fs::read_dir(repo_path.join("blobs")).unwrap().count(),
+            0
+        );
+        assert!(!repo_path.join("snapshots").exists());
+    }
+
+    #[tokio::test]
+    async fn corrupted_cache() {
+        let hub = Hub::serve_file(CONTENT.to_vec(), hex(&Sha256::digest(CONTENT)), 0);
+        let dir = tempdir().unwrap();
+        let mut builder = DownloadConfigBuilder::default();
+        builder
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .verify_cache(true);
+
+        let path = download(&builder.build().unwrap(), unbounded_channel().0)
+            .await
+            .unwrap();
+        write(&path, b"truncated").unwrap();
+
+        let error = download(&builder.build().unwrap(), unbounded_channel().0)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::ChecksumMismatch { .. }));
+
+        let config = builder.redownload_corrupted(true).build().unwrap();
+        let path = download(&config, unbounded_channel().0).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), CONTENT);
+        assert_eq!(hub.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn unverifiable_cache() {
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .cache_dir(dir.path())
+            .verify_cache(true)
+            .build()
+            .unwrap();
+        // A copy left by a file system without symlinks, nothing names its content
+        let repo_path = dir.path().join("models--owner--model");
+        let snapshot = repo_path.join("snapshots").join(COMMIT);
+        create_dir_all(&snapshot).unwrap();
+        write(snapshot.join("ggml.bin"), CONTENT).unwrap();
+        create_dir_all(repo_path.join("refs")).unwrap();
+        write(repo_path.join("refs").join("main"), COMMIT).unwrap();
+
+        let error = download_file(
+            "ggml.bin",
+            false,
+            true,
+            Arc::new(ProgressBarObserver::default()),
+            Repo::model("owner/model".into()),
+            &config,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, Error::Unverifiable { file, .. } if file == "ggml.bin"));
+
+        // The blob it was copied from vouches for its content
+        create_dir_all(repo_path.join("blobs")).unwrap();
+        let blob = repo_path.join("blobs").join(hex(&Sha256::digest(CONTENT)));
+        write(blob, CONTENT).unwrap();
+        download_file(
+            "ggml.bin",
+            false,
+            true,
+            Arc::new(ProgressBarObserver::default()),
+            Repo::model("owner/model".into()),
+            &config,
+        )
+        .await
+        .unwrap();
+    }
+
+    fn weights() -> Vec<u8> {
+        (0..100_000).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn resume_dropped_transfer() {
+        let content = weights();
+        let hub = Hub::serve_file(content.clone(), hex(&Sha256::digest(&content)), 2);
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .retries(3u32)
+            .retry_backoff(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let (tx, mut rx) = unbounded_channel();
+
+        let path = download(&config, tx).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), content);
+
+        let requests = hub.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(!requests[1].contains("range:"));
+        assert!(requests[2].contains("range: bytes=50000-"));
+        assert!(requests[3].contains("range: bytes=75000-"));
+
+        let mut retries = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let Event::DownloadRetry { attempt, delay, .. } = event {
+                retries.push((attempt, delay));
+            }
+        }
+        assert_eq!(
+            retries,
+            vec![(1, Duration::from_millis(1)), (2, Duration::from_millis(2))]
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_after_failure() {
+        let content = weights();
+        let hub = Hub::serve_file(content.clone(), hex(&Sha256::digest(&content)), 2);
+        let dir = tempdir().unwrap();
+        let mut builder = DownloadConfigBuilder::default();
+        builder
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .retries(1u32)
+            .retry_backoff(Duration::from_millis(1));
+
+        let error = download(&builder.build().unwrap(), unbounded_channel().0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Download(ApiError::TooManyRetries(_))
+        ));
+
+        // The partial data is kept and resumed by the next download
+        let path = download(&builder.build().unwrap(), unbounded_channel().0)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), content);
+        assert!(
+            hub.requests()
+                .last()
+                .unwrap()
+                .contains("range: bytes=75000-")
+        );
+    }
+
+    #[tokio::test]
+    async fn complete_part_file() {
+        let sha256 = hex(&Sha256::digest(CONTENT));
+        let hub = Hub::serve_file(CONTENT.to_vec(), sha256.clone(), 0);
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .build()
+            .unwrap();
+        // Interrupted between the end of the transfer and the rename
+        let blobs = dir.path().join("models--owner--model").join("blobs");
+        create_dir_all(&blobs).unwrap();
+        write(blobs.join(&sha256).with_extension(PART_EXTENSION), CONTENT).unwrap();
+
+        let path = download(&config, unbounded_channel().0).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), CONTENT);
+        // Only the metadata has been requested
+        assert_eq!(hub.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_downloads() {
+        let hub = Hub::serve();
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .build()
+            .unwrap();
+        let (leader_tx, mut leader_rx) = unbounded_channel();
+        let (follower_tx, mut follower_rx) = unbounded_channel();
+
+        let (leader, follower) =
+            tokio::join!(download(&config, leader_tx), download(&config, follower_tx));
+        assert_eq!(leader.unwrap(), follower.unwrap());
+        // A single metadata request and a single transfer
+        assert_eq!(hub.requests().len(), 2);
+        for rx in [&mut leader_rx, &mut follower_rx] {
+            let mut events = Vec::new();
+            while let Ok(event) = rx.try_recv() {
+                events.push(event);
+            }
+            assert!(events.first().unwrap().is_download_started());
+            assert!(events.last().unwrap().is_download_completed());
+        }
+    }
+
+    #[tokio::test]
+    async fn locked_by_another_process() {
+        let hub = Hub::serve();
+        let dir = tempdir().unwrap();
+        let config = DownloadConfigBuilder::default()
+            .endpoint(hub.address.clone())
+            .cache_dir(dir.path())
+            .build()
+            .unwrap();
+        let repo_path = dir.path().join("models--owner--model");
+        create_dir_all(repo_path.join(".locks")).unwrap();
+        let lock =
+            std::fs::File::create(repo_path.join(".locks").join(format!("{ETAG}.lock"))).unwrap();
+        lock.lock().unwrap();
+
+        let download = tokio::spawn(async move { download(&config, unbounded_channel().0).await });
+        sleep(Duration::from_millis(300)).await;
+        assert!(!download.is_finished());
+
+        // The other process completes the download and releases the lock
+        create_dir_all(repo_path.join("blobs")).unwrap();
+        write(repo_path.join("blobs").join(ETAG), CONTENT).unwrap();
+        lock.unlock().unwrap();
+
+        let path = download.await.unwrap().unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), CONTENT);
+        assert_eq!(hub.requests().len(), 1);
+    }
+}
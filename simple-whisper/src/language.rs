@@ -1,7 +1,14 @@
-use strum::{Display, EnumIs, EnumIter, EnumMessage, EnumString};
+use std::str::FromStr;
 
-/// Languages supported by the tokenizer
-#[derive(Default, Clone, Copy, Debug, EnumIs, EnumIter, EnumString, Display, EnumMessage)]
+use strum::{Display, EnumIs, EnumIter, EnumMessage, IntoEnumIterator, ParseError};
+
+/// Languages supported by the tokenizer, serialized as their ISO code with the `serde` feature.
+///
+/// They can be parsed from the whisper code, the ISO 639 codes, a BCP-47 tag
+/// (only the primary subtag is considered) and the English or native name.
+#[derive(
+    Default, Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIs, EnumIter, Display, EnumMessage,
+)]
 pub enum Language {
     #[default]
     #[strum(serialize = "en", message = "English - en")]
@@ -203,3 +210,386 @@ pub enum Language {
     #[strum(serialize = "su", message = "Sundanese - su")]
     Sundanese,
 }
+
+/// Writing systems of the languages, displayed as their ISO 15924 code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIs, EnumIter, Display)]
+pub enum Script {
+    #[strum(serialize = "Arab")]
+    Arabic,
+    #[strum(serialize = "Armn")]
+    Armenian,
+    #[strum(serialize = "Beng")]
+    Bengali,
+    #[strum(serialize = "Cyrl")]
+    Cyrillic,
+    #[strum(serialize = "Deva")]
+    Devanagari,
+    #[strum(serialize = "Ethi")]
+    Ethiopic,
+    #[strum(serialize = "Geor")]
+    Georgian,
+    #[strum(serialize = "Grek")]
+    Greek,
+    #[strum(serialize = "Gujr")]
+    Gujarati,
+    #[strum(serialize = "Guru")]
+    Gurmukhi,
+    #[strum(serialize = "Hani")]
+    Han,
+    #[strum(serialize = "Hebr")]
+    Hebrew,
+    /// Han and Kana
+    #[strum(serialize = "Jpan")]
+    Japanese,
+    #[strum(serialize = "Knda")]
+    Kannada,
+    #[strum(serialize = "Khmr")]
+    Khmer,
+    /// Hangul and Han
+    #[strum(serialize = "Kore")]
+    Korean,
+    #[strum(serialize = "Laoo")]
+    Lao,
+    #[strum(serialize = "Latn")]
+    Latin,
+    #[strum(serialize = "Mlym")]
+    Malayalam,
+    #[strum(serialize = "Mymr")]
+    Myanmar,
+    #[strum(serialize = "Sinh")]
+    Sinhala,
+    #[strum(serialize = "Taml")]
+    Tamil,
+    #[strum(serialize = "Telu")]
+    Telugu,
+    #[strum(serialize = "Thai")]
+    Thai,
+    #[strum(serialize = "Tibt")]
+    Tibetan,
+}
+
+impl Script {
+    /// The script is written from right to left
+    pub fn is_rtl(&self) -> bool {
+        matches!(self, Script::Arabic | Script::Hebrew)
+    }
+}
+
+/// Codes and names of a [Language]
+struct Metadata {
+    iso639_1: Option<&'static str>,
+    iso639_2: &'static str,
+    iso639_3: &'static str,
+    name: &'static str,
+    native_name: &'static str,
+    script: Script,
+}
+
+impl Language {
+    /// Two letters ISO 639-1 code, it differs from the whisper code for Javanese
+    pub fn iso639_1(&self) -> Option<&'static str> {
+        self.metadata().iso639_1
+    }
+
+    /// Three letters ISO 639-2 bibliographic code, as used by Matroska.
+    /// The terminology code is the [Language::iso639_3] one.
+    pub fn iso639_2(&self) -> &'static str {
+        self.metadata().iso639_2
+    }
+
+    /// Three letters ISO 639-3 code
+    pub fn iso639_3(&self) -> &'static str {
+        self.metadata().iso639_3
+    }
+
+    /// Shortest BCP-47 tag of the language, e.g. for WebVTT or HTML `lang` attributes
+    pub fn bcp47(&self) -> &'static str {
+        let metadata = self.metadata();
+        metadata.iso639_1.unwrap_or(metadata.iso639_3)
+    }
+
+    /// English name
+    pub fn name(&self) -> &'static str {
+        self.metadata().name
+    }
+
+    /// Name of the language in the language itself
+    pub fn native_name(&self) -> &'static str {
+        self.metadata().native_name
+    }
+
+    /// Writing system usually produced by the model
+    pub fn script(&self) -> Script {
+        self.metadata().script
+    }
+
+    /// The language is written from right to left
+    pub fn is_rtl(&self) -> bool {
+        self.script().is_rtl()
+    }
+
+    /// True if `value` is one of the codes or names of the language, ignoring the case
+    fn is_named(&self, value: &str) -> bool {
+        let metadata = self.metadata();
+        value.eq_ignore_ascii_case(&self.to_string())
+            || metadata
+                .iso639_1
+                .is_some_and(|code| value.eq_ignore_ascii_case(code))
+            || value.eq_ignore_ascii_case(metadata.iso639_2)
+            || value.eq_ignore_ascii_case(metadata.iso639_3)
+            || value.eq_ignore_ascii_case(metadata.name)
+            || value.to_lowercase() == metadata.native_name.to_lowercase()
+    }
+
+    fn metadata(&self) -> Metadata {
+        use Script::*;
+        let (iso639_1, iso639_2, iso639_3, name, native_name, script) = match self {
+            Language::English => (Some("en"), "eng", "eng", "English", "English", Latin),
+            Language::Chinese => (Some("zh"), "chi", "zho", "Chinese", "中文", Han),
+            Language::German => (Some("de"), "ger", "deu", "German", "Deutsch", Latin),
+            Language::Spanish => (Some("es"), "spa", "spa", "Spanish", "Español", Latin),
+            Language::Russian => (Some("ru"), "rus", "rus", "Russian", "Русский", Cyrillic),
+            Language::Korean => (Some("ko"), "kor", "kor", "Korean", "한국어", Korean),
+            Language::French => (Some("fr"), "fre", "fra", "French", "Français", Latin),
+            Language::Japanese => (Some("ja"), "jpn", "jpn", "Japanese", "日本語", Japanese),
+            Language::Portuguese => (Some("pt"), "por", "por", "Portuguese", "Português", Latin),
+            Language::Turkish => (Some("tr"), "tur", "tur", "Turkish", "Türkçe", Latin),
+            Language::Polish => (Some("pl"), "pol", "pol", "Polish", "Polski", Latin),
+            Language::Catalan => (Some("ca"), "cat", "cat", "Catalan", "Català", Latin),
+            Language::Dutch => (Some("nl"), "dut", "nld", "Dutch", "Nederlands", Latin),
+            Language::Arabic => (Some("ar"), "ara", "ara", "Arabic", "العربية", Arabic),
+            Language::Swedish => (Some("sv"), "swe", "swe", "Swedish", "Svenska", Latin),
+            Language::Italian => (Some("it"), "ita", "ita", "Italian", "Italiano", Latin),
+            Language::Indonesian => (
+                Some("id"),
+                "ind",
+                "ind",
+                "Indonesian",
+                "Bahasa Indonesia",
+                Latin,
+            ),
+            Language::Hindi => (Some("hi"), "hin", "hin", "Hindi", "हिन्दी", Devanagari),
+            Language::Finnish => (Some("fi"), "fin", "fin", "Finnish", "Suomi", Latin),
+            Language::Vietnamese => (Some("vi"), "vie", "vie", "Vietnamese", "Tiếng Việt", Latin),
+            Language::Hebrew => (Some("he"), "heb", "heb", "Hebrew", "עברית", Hebrew),
+            Language::Ukrainian => (
+                Some("uk"),
+                "ukr",
+                "ukr",
+                "Ukrainian",
+                "Українська",
+                Cyrillic,
+            ),
+            Language::Greek => (Some("el"), "gre", "ell", "Greek", "Ελληνικά", Greek),
+            Language::Malay => (Some("ms"), "may", "msa", "Malay", "Bahasa Melayu", Latin),
+            Language::Czech => (Some("cs"), "cze", "ces", "Czech", "Čeština", Latin),
+            Language::Romanian => (Some("ro"), "rum", "ron", "Romanian", "Română", Latin),
+            Language::Danish => (Some("da"), "dan", "dan", "Danish", "Dansk", Latin),
+            Language::Hungarian => (Some("hu"), "hun", "hun", "Hungarian", "Magyar", Latin),
+            Language::Tamil => (Some("ta"), "tam", "tam", "Tamil", "தமிழ்", Tamil),
+            Language::Norwegian => (Some("no"), "nor", "nor", "Norwegian", "Norsk", Latin),
+            Language::Thai => (Some("th"), "tha", "tha", "Thai", "ไทย", Thai),
+            Language::Urdu => (Some("ur"), "urd", "urd", "Urdu", "اردو", Arabic),
+            Language::Croatian => (Some("hr"), "hrv", "hrv", "Croatian", "Hrvatski", Latin),
+            Language::Bulgarian => (Some("bg"), "bul", "bul", "Bulgarian", "Български", Cyrillic),
+            Language::Lithuanian => (Some("lt"), "lit", "lit", "Lithuanian", "Lietuvių", Latin),
+            Language::Latin => (Some("la"), "lat", "lat", "Latin", "Latina", Latin),
+            Language::Maori => (Some("mi"), "mao", "mri", "Maori", "Māori", Latin),
+            Language::Malayalam => (Some("ml"), "mal", "mal", "Malayalam", "മലയാളം", Malayalam),
+            Language::Welsh => (Some("cy"), "wel", "cym", "Welsh", "Cymraeg", Latin),
+            Language::Slovak => (Some("sk"), "slo", "slk", "Slovak", "Slovenčina", Latin),
+            Language::Telugu => (Some("te"), "tel", "tel", "Telugu", "తెలుగు", Telugu),
+            Language::Persian => (Some("fa"), "per", "fas", "Persian", "فارسی", Arabic),
+            Language::Latvian => (Some("lv"), "lav", "lav", "Latvian", "Latviešu", Latin),
+            Language::Bengali => (Some("bn"), "ben", "ben", "Bengali", "বাংলা", Bengali),
+            Language::Serbian => (Some("sr"), "srp", "srp", "Serbian", "Српски", Cyrillic),
+            Language::Azerbaijani => (
+                Some("az"),
+                "aze",
+                "aze",
+                "Azerbaijani",
+                "Azərbaycanca",
+                Latin,
+            ),
+            Language::Slovenian => (Some("sl"), "slv", "slv", "Slovenian", "Slovenščina", Latin),
+            Language::Kannada => (Some("kn"), "kan", "kan", "Kannada", "ಕನ್ನಡ", Kannada),
+            Language::Estonian => (Some("et"), "est", "est", "Estonian", "Eesti", Latin),
+            Language::Macedonian => (
+                Some("mk"),
+                "mac",
+                "mkd",
+                "Macedonian",
+                "Македонски",
+                Cyrillic,
+            ),
+            Language::Breton => (Some("br"), "bre", "bre", "Breton", "Brezhoneg", Latin),
+            Language::Basque => (Some("eu"), "baq", "eus", "Basque", "Euskara", Latin),
+            Language::Icelandic => (Some("is"), "ice", "isl", "Icelandic", "Íslenska", Latin),
+            Language::Armenian => (Some("hy"), "arm", "hye", "Armenian", "Հայերեն", Armenian),
+            Language::Nepali => (Some("ne"), "nep", "nep", "Nepali", "नेपाली", Devanagari),
+            Language::Mongolian => (Some("mn"), "mon", "mon", "Mongolian", "Монгол", Cyrillic),
+            Language::Bosnian => (Some("bs"), "bos", "bos", "Bosnian", "Bosanski", Latin),
+            Language::Kazakh => (Some("kk"), "kaz", "kaz", "Kazakh", "Қазақша", Cyrillic),
+            Language::Albanian => (Some("sq"), "alb", "sqi", "Albanian", "Shqip", Latin),
+            Language::Swahili => (Some("sw"), "swa", "swa", "Swahili", "Kiswahili", Latin),
+            Language::Galician => (Some("gl"), "glg", "glg", "Galician", "Galego", Latin),
+            Language::Marathi => (Some("mr"), "mar", "mar", "Marathi", "मराठी", Devanagari),
+            Language::Punjabi => (Some("pa"), "pan", "pan", "Punjabi", "ਪੰਜਾਬੀ", Gurmukhi),
+            Language::Sinhala => (Some("si"), "sin", "sin", "Sinhala", "සිංහල", Sinhala),
+            Language::Khmer => (Some("km"), "khm", "khm", "Khmer", "ខ្មែរ", Khmer),
+            Language::Shona => (Some("sn"), "sna", "sna", "Shona", "ChiShona", Latin),
+            Language::Yoruba => (Some("yo"), "yor", "yor", "Yoruba", "Yorùbá", Latin),
+            Language::Somali => (Some("so"), "som", "som", "Somali", "Soomaali", Latin),
+            Language::Afrikaans => (Some("af"), "afr", "afr", "Afrikaans", "Afrikaans", Latin),
+            Language::Occitan => (Some("oc"), "oci", "oci", "Occitan", "Occitan", Latin),
+            Language::Georgian => (Some("ka"), "geo", "kat", "Georgian", "ქართული", Georgian),
+            Language::Belarusian => (
+                Some("be"),
+                "bel",
+                "bel",
+                "Belarusian",
+                "Беларуская",
+                Cyrillic,
+            ),
+            Language::Tajik => (Some("tg"), "tgk", "tgk", "Tajik", "Тоҷикӣ", Cyrillic),
+            Language::Sindhi => (Some("sd"), "snd", "snd", "Sindhi", "سنڌي", Arabic),
+            Language::Gujarati => (Some("gu"), "guj", "guj", "Gujarati", "ગુજરાતી", Gujarati),
+            Language::Amharic => (Some("am"), "amh", "amh", "Amharic", "አማርኛ", Ethiopic),
+            Language::Yiddish => (Some("yi"), "yid", "yid", "Yiddish", "ייִדיש", Hebrew),
+            Language::Lao => (Some("lo"), "lao", "lao", "Lao", "ລາວ", Lao),
+            Language::Uzbek => (Some("uz"), "uzb", "uzb", "Uzbek", "Oʻzbekcha", Latin),
+            Language::Faroese => (Some("fo"), "fao", "fao", "Faroese", "Føroyskt", Latin),
+            Language::HaitianCreole => (
+                Some("ht"),
+                "hat",
+                "hat",
+                "Haitian Creole",
+                "Kreyòl ayisyen",
+                Latin,
+            ),
+            Language::Pashto => (Some("ps"), "pus", "pus", "Pashto", "پښتو", Arabic),
+            Language::Turkmen => (Some("tk"), "tuk", "tuk", "Turkmen", "Türkmençe", Latin),
+            Language::Nynorsk => (
+                Some("nn"),
+                "nno",
+                "nno",
+                "Norwegian Nynorsk",
+                "Nynorsk",
+                Latin,
+            ),
+            Language::Maltese => (Some("mt"), "mlt", "mlt", "Maltese", "Malti", Latin),
+            Language::Sanskrit => (Some("sa"), "san", "san", "Sanskrit", "संस्कृतम्", Devanagari),
+            Language::Luxembourgish => (
+                Some("lb"),
+                "ltz",
+                "ltz",
+                "Luxembourgish",
+                "Lëtzebuergesch",
+                Latin,
+            ),
+            Language::Myanmar => (Some("my"), "bur", "mya", "Burmese", "မြန်မာ", Myanmar),
+            Language::Tibetan => (Some("bo"), "tib", "bod", "Tibetan", "བོད་སྐད་", Tibetan),
+            Language::Tagalog => (Some("tl"), "tgl", "tgl", "Tagalog", "Tagalog", Latin),
+            Language::Malagasy => (Some("mg"), "mlg", "mlg", "Malagasy", "Malagasy", Latin),
+            Language::Assamese => (Some("as"), "asm", "asm", "Assamese", "অসমীয়া", Bengali),
+            Language::Tatar => (Some("tt"), "tat", "tat", "Tatar", "Татарча", Cyrillic),
+            Language::Hawaiian => (None, "haw", "haw", "Hawaiian", "ʻŌlelo Hawaiʻi", Latin),
+            Language::Lingala => (Some("ln"), "lin", "lin", "Lingala", "Lingála", Latin),
+            Language::Hausa => (Some("ha"), "hau", "hau", "Hausa", "Hausa", Latin),
+            Language::Bashkir => (Some("ba"), "bak", "bak", "Bashkir", "Башҡортса", Cyrillic),
+            Language::Javanese => (Some("jv"), "jav", "jav", "Javanese", "Basa Jawa", Latin),
+            Language::Sundanese => (Some("su"), "sun", "sun", "Sundanese", "Basa Sunda", Latin),
+        };
+        Metadata {
+            iso639_1,
+            iso639_2,
+            iso639_3,
+            name,
+            native_name,
+            script,
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // BCP-47 tags such as `pt-BR` or `sr-Latn`
+        let primary = s.split(['-', '_']).next().unwrap_or(s);
+        Language::iter()
+            .find(|language| language.is_named(s))
+            .or_else(|| Language::iter().find(|language| language.is_named(primary)))
+            .ok_or(ParseError::VariantNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_and_names() {
+        let german = Language::German;
+        assert_eq!(german.to_string(), "de");
+        assert_eq!(german.iso639_1(), Some("de"));
+        assert_eq!(german.iso639_2(), "ger");
+        assert_eq!(german.iso639_3(), "deu");
+        assert_eq!(german.native_name(), "Deutsch");
+        assert_eq!(german.script(), Script::Latin);
+
+        assert_eq!(Language::Javanese.to_string(), "jw");
+        assert_eq!(Language::Javanese.bcp47(), "jv");
+        assert_eq!(Language::Hawaiian.iso639_1(), None);
+        assert_eq!(Language::Hawaiian.bcp47(), "haw");
+        assert_eq!(Language::Serbian.script().to_string(), "Cyrl");
+    }
+
+    #[test]
+    fn right_to_left() {
+        let rtl: Vec<_> = Language::iter().filter(Language::is_rtl).collect();
+        assert_eq!(
+            rtl,
+            [
+                Language::Arabic,
+                Language::Hebrew,
+                Language::Urdu,
+                Language::Persian,
+                Language::Sindhi,
+                Language::Yiddish,
+                Language::Pashto,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_any_form() {
+        for value in ["it", "ita", "Italian", "italiano", "it-CH", "IT_it"] {
+            assert_eq!(Language::from_str(value), Ok(Language::Italian), "{value}");
+        }
+        assert_eq!(Language::from_str("jv"), Ok(Language::Javanese));
+        assert_eq!(Language::from_str("ger"), Ok(Language::German));
+        assert_eq!(Language::from_str("zh-Hant-TW"), Ok(Language::Chinese));
+        assert_eq!(
+            Language::from_str("Norwegian Nynorsk"),
+            Ok(Language::Nynorsk)
+        );
+        assert_eq!(Language::from_str("עברית"), Ok(Language::Hebrew));
+        assert!(Language::from_str("xx").is_err());
+        assert!(Language::from_str("").is_err());
+
+        for language in Language::iter() {
+            for value in [
+                language.to_string().as_str(),
+                language.iso639_2(),
+                language.iso639_3(),
+                language.bcp47(),
+                language.name(),
+                language.native_name(),
+            ] {
+                assert_eq!(Language::from_str(value), Ok(language), "{value}");
+            }
+        }
+    }
+}
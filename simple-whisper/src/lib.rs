@@ -2,34 +2,82 @@ use std::{
     fs::File,
     io::{self, BufReader},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, mpsc::sync_channel},
+    thread,
     time::Duration,
 };
 
 use derive_builder::Builder;
 
+mod align;
+mod backpressure;
+mod bundle;
+mod cache;
 mod download;
+mod export;
+mod group;
+mod header;
+#[cfg(feature = "tokio")]
+mod hub;
 mod language;
 mod model;
+mod observer;
+mod recommend;
+mod registry;
+#[cfg(feature = "serde")]
+mod serialize;
+mod source;
+mod subtitle;
+mod suppress;
 mod transcribe;
-
-use download::ProgressType;
-pub use language::Language;
-pub use model::Model;
+#[cfg(feature = "tokio")]
+mod transcriber;
+
+use align::align_reference;
+pub use align::{AlignedWord, Alignment, UnalignedSpan};
+#[cfg(feature = "tokio")]
+use backpressure::DownloadEvents;
+pub use backpressure::{Backpressure, DEFAULT_CHANNEL_CAPACITY};
+pub use bundle::{BundleManifest, BundledModel, export_bundle, import_bundle};
+pub use cache::{CachedModel, ModelCache};
+use download::offline_from_env;
+pub use download::{DownloadConfig, DownloadConfigBuilder, DownloadConfigBuilderError};
+pub use export::{ExportFormat, Exporter, ExporterBuilder, ExporterBuilderError};
+pub use group::{
+    PARAGRAPH_PAUSE, Paragraph, ParagraphGrouper, SentenceGrouper, Sentences, paragraphs, sentences,
+};
+pub use header::ModelHeader;
+pub use language::{Language, Script};
+pub use model::{Model, ModelFamily, ModelInfo, Quantization};
+pub use observer::{ChannelObserver, DownloadObserver, ProgressBarObserver, TracingObserver};
+pub use recommend::{
+    Constraints, ConstraintsBuilder, ConstraintsBuilderError, Preference, Recommendation,
+};
+pub use registry::{MANIFEST_ENV, ModelManifest, RegisteredModel, registered_models};
 use rodio::{Decoder, Source, source::UniformSourceIterator};
+pub use source::ModelSource;
 use strum::{Display, EnumIs};
+pub use subtitle::{SubtitleFormat, retime};
+pub use suppress::Suppress;
 use thiserror::Error;
+#[cfg(feature = "tokio")]
 use tokio::{
-    spawn,
-    sync::{Notify, mpsc::unbounded_channel},
+    runtime, spawn,
+    sync::{Notify, mpsc::channel},
     task::spawn_blocking,
 };
 pub use transcribe::TranscribeBuilderError;
+#[cfg(feature = "tokio")]
+pub use transcriber::{
+    Transcriber, TranscriberConfig, TranscriberConfigBuilder, TranscriberConfigBuilderError,
+};
 
-use tokio_stream::{Stream, wrappers::UnboundedReceiverStream};
-use transcribe::TranscribeBuilder;
+#[cfg(feature = "tokio")]
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+use transcribe::{EventSink, TranscribeBuilder, check_memory};
 use whisper_rs::WhisperError;
 
+#[cfg(feature = "tokio")]
 type Barrier = Arc<Notify>;
 
 pub const SAMPLE_RATE: u32 = 16000;
@@ -39,21 +87,53 @@ pub const SAMPLE_RATE: u32 = 16000;
 #[builder(setter(into), build_fn(validate = "Self::validate"))]
 pub struct Whisper {
     language: Language,
-    model: Model,
+    model: ModelSource,
     #[builder(default = "false")]
     progress_bar: bool,
     #[builder(default = "false")]
     force_download: bool,
+    /// Only use cached or local model files, never touching the network.
+    /// Defaults to the value of the `HF_HUB_OFFLINE` environment variable.
+    #[builder(default = "offline_from_env()")]
+    offline: bool,
+    /// Hub endpoint, cache directory, token, revision and proxy used to fetch the model
+    #[builder(default)]
+    download_config: DownloadConfig,
     #[builder(default = "false")]
     force_single_segment: bool,
+    /// Load the model even if the host does not seem to have the memory to run it
+    #[builder(default = "false")]
+    ignore_memory_check: bool,
+    /// Events buffered for a consumer falling behind
+    #[builder(default = "DEFAULT_CHANNEL_CAPACITY")]
+    channel_capacity: usize,
+    /// What happens once `channel_capacity` events are waiting
+    #[builder(default)]
+    backpressure: Backpressure,
+    /// Suppress the blank outputs at the beginning of the sampling
+    #[builder(default = "true")]
+    suppress_blank: bool,
+    /// Suppress the non-speech tokens: symbols, music notes and bracketed annotations
+    #[builder(default = "false")]
+    suppress_non_speech: bool,
+    /// Texts or tokens the model must not output, e.g. `"♪"` or a recurring junk phrase
+    #[builder(default)]
+    suppress: Vec<Suppress>,
+    #[builder(default, setter(custom))]
+    download_observer: Option<Arc<dyn DownloadObserver>>,
 }
 
 /// Error conditions
 #[derive(Error, Debug)]
 pub enum Error {
     /// Error that can occur during model files download from huggingface
+    #[cfg(feature = "tokio")]
     #[error(transparent)]
     Download(#[from] hf_hub::api::tokio::ApiError),
+    /// Error that can occur during the blocking model downloads of the `ureq` feature
+    #[cfg(feature = "ureq")]
+    #[error(transparent)]
+    BlockingDownload(#[from] hf_hub::api::sync::ApiError),
     #[error(transparent)]
     Io(#[from] io::Error),
     /// Error that can occur during audio file decoding phase
@@ -67,15 +147,67 @@ pub enum Error {
     ComputeBuilder(#[from] TranscribeBuilderError),
     #[error(transparent)]
     Whisper(#[from] WhisperError),
+    /// Malformed subtitle file
+    #[error("Invalid subtitle at line {line}: {reason}")]
+    Subtitle { line: usize, reason: String },
+    /// The model file is not cached and offline mode forbids downloading it
+    #[error("Model file {file} is not available offline, expected at {}", path.display())]
+    ModelNotAvailableOffline { file: String, path: PathBuf },
+    /// The model file is not cached and the crate has been built without a download
+    /// backend: the `tokio` or `ureq` feature
+    #[error("Model file {file} is not cached and downloads are not enabled, expected at {}", path.display())]
+    DownloadsDisabled { file: String, path: PathBuf },
+    /// The SHA-256 of the model file differs from the one published by the hub
+    #[error("Checksum mismatch for {file}: expected {expected}, found {found}")]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        found: String,
+    },
+    /// Verification of the cache was requested, but no SHA-256 is known for the file
+    #[error("No checksum is known for {file} at {}, it can not be verified", path.display())]
+    Unverifiable { file: String, path: PathBuf },
+    /// The model source can not be parsed
+    #[error("Invalid model source {0}")]
+    ModelSource(String),
+    /// The model manifest can not be parsed or declares invalid models
+    #[error("Invalid model manifest {0}")]
+    Manifest(String),
+    /// The model bundle can not be created or is malformed
+    #[error("Invalid model bundle: {0}")]
+    Bundle(String),
+    /// Running the model would exceed the memory available on the host
+    #[error("Insufficient memory: the model needs about {required} bytes, {available} available")]
+    InsufficientMemory { required: u64, available: u64 },
 }
 
-/// Events generated by the [Whisper::transcribe] method
+/// Events generated by the [Whisper::transcribe] method.
+///
+/// With the `serde` feature an event is an object tagged by `event`, the durations are
+/// in seconds: `{"event":"segment","start_offset":0.0,"end_offset":2.5,"percentage":0.2,"transcription":"Hello"}`
 #[derive(Clone, Debug, Display, EnumIs)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "event", rename_all = "snake_case")
+)]
 pub enum Event {
     #[strum(to_string = "Downloading {file}")]
     DownloadStarted { file: String },
     #[strum(to_string = "{file} has been downloaded")]
     DownloadCompleted { file: String },
+    /// The transfer has been interrupted and will be resumed after `delay`
+    #[strum(to_string = "Retrying {file} in {delay:#?}, attempt {attempt}: {reason}")]
+    DownloadRetry {
+        file: String,
+        attempt: u32,
+        #[cfg_attr(feature = "serde", serde(with = "serialize::seconds"))]
+        delay: Duration,
+        reason: String,
+    },
+    /// Integrity check of the model file
+    #[strum(to_string = "Verifying {file}")]
+    Verifying { file: String },
     #[strum(
         to_string = "Downloading {file} --> {percentage} {elapsed_time:#?} | {remaining_time:#?}"
     )]
@@ -87,25 +219,76 @@ pub enum Event {
         percentage: f32,
 
         /// Time elapsed since the download as being started
+        #[cfg_attr(feature = "serde", serde(with = "serialize::seconds"))]
         elapsed_time: Duration,
 
         /// Estimated time to complete the download
+        #[cfg_attr(feature = "serde", serde(with = "serialize::seconds"))]
         remaining_time: Duration,
     },
+    /// A [Transcriber] is loading a new model, the current one keeps serving
+    #[strum(to_string = "Loading model {model}")]
+    ModelLoading { model: String },
+    /// The new jobs of a [Transcriber] run on `model`, the ones in flight finish on `previous`
+    #[strum(to_string = "Swapped model {previous} for {model}")]
+    ModelSwapped { previous: String, model: String },
     /// Audio chunk transcript
     #[strum(to_string = "{transcription}")]
     Segment {
+        #[cfg_attr(feature = "serde", serde(with = "serialize::seconds"))]
         start_offset: Duration,
+        #[cfg_attr(feature = "serde", serde(with = "serialize::seconds"))]
         end_offset: Duration,
         percentage: f32,
         transcription: String,
     },
 }
 
+/// A transcribed audio chunk, as carried by [Event::Segment]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub start_offset: Duration,
+    pub end_offset: Duration,
+    pub transcription: String,
+    /// Label of the person speaking, when known
+    pub speaker: Option<String>,
+}
+
+impl TryFrom<Event> for Segment {
+    type Error = Event;
+
+    fn try_from(value: Event) -> Result<Self, Self::Error> {
+        match value {
+            Event::Segment {
+                start_offset,
+                end_offset,
+                transcription,
+                ..
+            } => Ok(Segment {
+                start_offset,
+                end_offset,
+                transcription,
+                speaker: None,
+            }),
+            other => Err(other),
+        }
+    }
+}
+
 impl WhisperBuilder {
+    /// Observer notified of the model download, along with the progress bar and the events
+    pub fn download_observer(&mut self, observer: Arc<dyn DownloadObserver>) -> &mut Self {
+        self.download_observer = Some(Some(observer));
+        self
+    }
+
     fn validate(&self) -> Result<(), WhisperBuilderError> {
+        let config = self.download_config.clone().unwrap_or_default();
         if self.language.as_ref().is_some_and(|l| !l.is_english())
-            && self.model.as_ref().is_some_and(|m| !m.is_multilingual())
+            && self
+                .model
+                .as_ref()
+                .is_some_and(|m| m.is_multilingual_with_config(&config) == Some(false))
         {
             let err = format!(
                 "The requested language {} is not compatible with {} model",
@@ -114,15 +297,25 @@ impl WhisperBuilder {
             );
             return Err(WhisperBuilderError::ValidationError(err));
         }
+        if self.channel_capacity == Some(0) {
+            return Err(WhisperBuilderError::ValidationError(
+                "The event channel capacity must be greater than 0".into(),
+            ));
+        }
         Ok(())
     }
 }
 
+/// Async API, available with the `tokio` feature
+#[cfg(feature = "tokio")]
 impl Whisper {
     /// Transcribe an audio file into text.
+    ///
+    /// At most `channel_capacity` events wait in the stream, once it is full the
+    /// [Backpressure] policy applies.
     pub fn transcribe(self, path: impl AsRef<Path>) -> impl Stream<Item = Result<Event, Error>> {
-        let (tx, rx) = unbounded_channel();
-        let (tx_event, mut rx_event) = unbounded_channel();
+        let (tx, rx) = channel(self.channel_capacity);
+        let (events, rx_event) = DownloadEvents::observer();
 
         let wait_download = Barrier::default();
         let download_completed = wait_download.clone();
@@ -131,58 +324,191 @@ impl Whisper {
 
         // Download events forwarder
         let tx_forwarder = tx.clone();
+        let backpressure = self.backpressure;
         spawn(async move {
-            while let Some(msg) = rx_event.recv().await {
-                let _ = tx_forwarder.send(Ok(msg));
-            }
+            backpressure.forward(rx_event, &tx_forwarder).await;
             wait_download.notify_one();
         });
 
         spawn(async move {
             // Download model data from Hugging Face
-            let progress = if self.progress_bar {
-                drop(tx_event);
-                ProgressType::ProgressBar
-            } else {
-                ProgressType::Callback(tx_event)
-            };
+            let observer = self.observers(Some(Arc::new(events)));
             let model = self
                 .model
-                .internal_download_model(self.force_download, progress)
+                .internal_download_model(
+                    self.force_download,
+                    self.offline,
+                    observer,
+                    &self.download_config,
+                )
                 .await;
             download_completed.notified().await;
 
-            spawn_blocking(move || {
-                // Load audio file
-                let audio = Self::load_audio(path);
-
-                match audio.map(|audio| (audio, model)) {
-                    Ok((audio, Ok(model_files))) => {
-                        match TranscribeBuilder::default()
-                            .language(self.language)
-                            .audio(audio)
-                            .single_segment(self.force_single_segment)
-                            .tx(tx.clone())
-                            .model(model_files)
-                            .build()
-                        {
-                            Ok(compute) => compute.transcribe(),
-                            Err(err) => {
-                                let _ = tx.send(Err(err.into()));
-                            }
-                        }
-                    }
-                    Ok((_, Err(err))) => {
-                        let _ = tx.send(Err(err));
-                    }
-                    Err(err) => {
-                        let _ = tx.send(Err(err));
-                    }
-                }
-            });
+            let sink = self.backpressure.blocking_sink(tx);
+            spawn_blocking(move || self.transcribe_model(model, path, sink));
         });
 
-        UnboundedReceiverStream::new(rx)
+        ReceiverStream::new(rx)
+    }
+
+    /// Compute the timing of a known transcript of the audio file.
+    ///
+    /// The audio is transcribed with token level timestamps, then the recognized words
+    /// are aligned to the reference text.
+    pub async fn align(
+        self,
+        path: impl AsRef<Path>,
+        reference: impl Into<String>,
+    ) -> Result<Alignment, Error> {
+        let path = path.as_ref().into();
+        let reference = reference.into();
+
+        // Download events are not reported while aligning
+        let observer = self.observers(None);
+        let model = self
+            .model
+            .internal_download_model(
+                self.force_download,
+                self.offline,
+                observer,
+                &self.download_config,
+            )
+            .await?;
+
+        spawn_blocking(move || self.align_model(model, path, reference))
+            .await
+            .map_err(io::Error::from)?
+    }
+}
+
+impl Whisper {
+    /// Transcribe an audio file into text without an async runtime.
+    ///
+    /// The transcription runs on its own thread, the iterator blocks waiting for its events.
+    /// Without the `tokio` feature the model is downloaded with the `ureq` feature, without either
+    /// one a model missing from the cache fails with [Error::DownloadsDisabled].
+    pub fn transcribe_blocking(
+        self,
+        path: impl AsRef<Path>,
+    ) -> impl Iterator<Item = Result<Event, Error>> {
+        let (tx, rx) = sync_channel(self.channel_capacity);
+        let path = path.as_ref().into();
+
+        thread::spawn(move || {
+            let sink = self.backpressure.sink(tx);
+            let model = self.model_blocking(&sink);
+            self.transcribe_model(model, path, sink);
+        });
+
+        rx.into_iter()
+    }
+
+    /// Same as [Whisper::align], blocking the calling thread.
+    ///
+    /// It must not be called from within an async runtime.
+    pub fn align_blocking(
+        self,
+        path: impl AsRef<Path>,
+        reference: impl Into<String>,
+    ) -> Result<Alignment, Error> {
+        // Download events are not reported while aligning
+        let model = self.model_blocking(&(Arc::new(|_| {}) as EventSink))?;
+        self.align_model(model, path.as_ref().into(), reference.into())
+    }
+
+    /// Make the model file available, reporting the download on `sink`
+    fn model_blocking(&self, sink: &EventSink) -> Result<PathBuf, Error> {
+        let sink = sink.clone();
+        let events = ChannelObserver::from_fn(move |event| sink(Ok(event)));
+        let observer = self.observers(Some(Arc::new(events)));
+        #[cfg(feature = "tokio")]
+        {
+            runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(self.model.internal_download_model(
+                    self.force_download,
+                    self.offline,
+                    observer,
+                    &self.download_config,
+                ))
+        }
+        #[cfg(not(feature = "tokio"))]
+        self.model.blocking_download_model(
+            self.force_download,
+            self.offline,
+            observer.as_ref(),
+            &self.download_config,
+        )
+    }
+
+    /// Observers of the model download: the progress bar, the custom observer and `events`
+    fn observers(&self, events: Option<Arc<dyn DownloadObserver>>) -> Arc<dyn DownloadObserver> {
+        let mut observers: Vec<Arc<dyn DownloadObserver>> = Vec::new();
+        if self.progress_bar {
+            observers.push(Arc::new(ProgressBarObserver::default()));
+        }
+        observers.extend(self.download_observer.clone());
+        observers.extend(events);
+        Arc::new(observers)
+    }
+
+    /// Run the transcription on the model file, sending the segments to `sink`
+    fn transcribe_model(self, model: Result<PathBuf, Error>, path: PathBuf, sink: EventSink) {
+        let model = model.and_then(|model| self.check_memory(&model).map(|_| model));
+        // Load audio file
+        let audio = Self::load_audio(path);
+
+        match audio.map(|audio| (audio, model)) {
+            Ok((audio, Ok(model_files))) => {
+                match TranscribeBuilder::default()
+                    .language(self.language)
+                    .audio(audio)
+                    .single_segment(self.force_single_segment)
+                    .suppress_blank(self.suppress_blank)
+                    .suppress_non_speech(self.suppress_non_speech)
+                    .suppress(self.suppress.clone())
+                    .tx(sink.clone())
+                    .model(model_files)
+                    .build()
+                {
+                    Ok(compute) => compute.transcribe(),
+                    Err(err) => sink(Err(err.into())),
+                }
+            }
+            Ok((_, Err(err))) => sink(Err(err)),
+            Err(err) => sink(Err(err)),
+        }
+    }
+
+    /// Align the reference text on the words recognized by the model
+    fn align_model(
+        self,
+        model: PathBuf,
+        path: PathBuf,
+        reference: String,
+    ) -> Result<Alignment, Error> {
+        self.check_memory(&model)?;
+        let audio = Self::load_audio(path)?;
+        let words = TranscribeBuilder::default()
+            .language(self.language)
+            .audio(audio)
+            .single_segment(self.force_single_segment)
+            .suppress_blank(self.suppress_blank)
+            .suppress_non_speech(self.suppress_non_speech)
+            .suppress(self.suppress.clone())
+            .tx(Arc::new(|_| {}) as EventSink)
+            .model(model)
+            .build()?
+            .words()?;
+        Ok(align_reference(&reference, &words))
+    }
+
+    fn check_memory(&self, model: &Path) -> Result<(), Error> {
+        if self.ignore_memory_check {
+            return Ok(());
+        }
+        check_memory(model)
     }
 
     fn load_audio(path: PathBuf) -> Result<(Vec<f32>, Duration), Error> {
@@ -209,6 +535,7 @@ impl Whisper {
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "tokio")]
     use tokio_stream::StreamExt;
 
     use super::*;
@@ -229,6 +556,29 @@ mod tests {
         assert!(matches!(error, WhisperBuilderError::ValidationError(_)));
     }
 
+    #[test]
+    fn incompatible_lang_cached_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let english = std::fs::read(header::tests::ggml_file(51864, 1).path()).unwrap();
+        download::tests::store(dir.path(), &Model::TinyEn, &english);
+        let coordinates = Model::TinyEn.hf_coordinates();
+        let error = WhisperBuilder::default()
+            .language(Language::Italian)
+            .model(ModelSource::hugging_face(
+                download::repo_id(&coordinates.repo),
+                coordinates.model,
+            ))
+            .download_config(
+                DownloadConfigBuilder::default()
+                    .cache_dir(dir.path())
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, WhisperBuilderError::ValidationError(_)));
+    }
+
     #[test]
     fn compatible_lang_model() {
         WhisperBuilder::default()
@@ -238,6 +588,30 @@ mod tests {
             .unwrap();
     }
 
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn download_observers() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let whisper = WhisperBuilder::default()
+            .language(Language::English)
+            .model(Model::Tiny)
+            .progress_bar(true)
+            .download_observer(Arc::new(ChannelObserver::from_fn(move |event| {
+                let _ = tx.send(event);
+            })))
+            .build()
+            .unwrap();
+        let (tx_event, mut rx_event) = tokio::sync::mpsc::unbounded_channel();
+        let observer = whisper.observers(Some(Arc::new(ChannelObserver::new(tx_event))));
+        observer.init("ggml-tiny.bin", 10);
+        observer.finish("ggml-tiny.bin");
+
+        // Both the custom observer and the event stream follow the download
+        assert!(rx.try_iter().any(|event| event.is_download_completed()));
+        assert!(rx_event.try_recv().unwrap().is_download_started());
+    }
+
+    #[cfg(feature = "tokio")]
     #[ignore]
     #[tokio::test]
     async fn simple_transcribe_ok() {
@@ -254,4 +628,40 @@ mod tests {
             println!("{msg:?}");
         }
     }
+
+    #[cfg(feature = "tokio")]
+    #[ignore]
+    #[tokio::test]
+    async fn simple_align_ok() {
+        let alignment = WhisperBuilder::default()
+            .language(Language::English)
+            .model(Model::Tiny)
+            .progress_bar(true)
+            .build()
+            .unwrap()
+            .align(
+                test_file!("samples_jfk.wav"),
+                "And so my fellow Americans, ask not what your country can do for you,\nask what you can do for your country.",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(alignment.segments.len(), 2);
+        println!("{alignment:?}");
+    }
+
+    #[ignore]
+    #[test]
+    fn simple_transcribe_blocking_ok() {
+        let events: Vec<_> = WhisperBuilder::default()
+            .language(Language::English)
+            .model(Model::Tiny)
+            .build()
+            .unwrap()
+            .transcribe_blocking(test_file!("samples_jfk.wav"))
+            .collect();
+
+        assert!(events.iter().all(Result::is_ok));
+        assert!(events.iter().flatten().any(Event::is_segment));
+    }
 }
@@ -1,21 +1,128 @@
 use std::path::PathBuf;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
 
-use hf_hub::{Cache, Repo};
+use hf_hub::Repo;
 use strum::{Display, EnumIter, EnumString};
+#[cfg(feature = "tokio")]
 use tokio::sync::mpsc::UnboundedSender;
 
+#[cfg(feature = "tokio")]
 use crate::{
-    Error, Event,
-    download::{ProgressType, download_file},
+    ChannelObserver, DownloadObserver, Error, Event, ProgressBarObserver,
+    download::offline_from_env, hub::download_file,
 };
+use crate::{DownloadConfig, ModelHeader};
 
-struct HFCoordinates {
-    repo: Repo,
-    model: String,
+/// One megabyte
+const MB: u64 = 1_000_000;
+
+/// Threads assumed by the memory estimate of [ModelInfo]
+const REFERENCE_THREADS: usize = 4;
+
+/// SHA-256 of the model files, as named by the LFS pointers of `ggerganov/whisper.cpp`.
+///
+/// They are the ground truth of downloads, cache hits and bundle imports; the files of
+/// models missing here can only be checked against the name of their blob.
+/// The `published_checksums` test compares it with the hub and prints the missing entries.
+const MODEL_SHA256: &[(Model, &str)] = &[];
+
+pub(crate) struct HFCoordinates {
+    pub(crate) repo: Repo,
+    pub(crate) model: String,
+}
+
+/// Generation and size of the whisper architecture
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+pub enum ModelFamily {
+    Tiny,
+    Base,
+    Small,
+    Medium,
+    #[strum(to_string = "Large V1")]
+    LargeV1,
+    #[strum(to_string = "Large V2")]
+    LargeV2,
+    #[strum(to_string = "Large V3")]
+    LargeV3,
+    #[strum(to_string = "Large V3 Turbo")]
+    LargeV3Turbo,
+}
+
+impl ModelFamily {
+    /// Transcription speed compared to the large models, as published by OpenAI
+    fn relative_speed(&self) -> f32 {
+        match self {
+            ModelFamily::Tiny => 10.,
+            ModelFamily::Base => 7.,
+            ModelFamily::Small => 4.,
+            ModelFamily::Medium => 2.,
+            ModelFamily::LargeV1 | ModelFamily::LargeV2 | ModelFamily::LargeV3 => 1.,
+            ModelFamily::LargeV3Turbo => 8.,
+        }
+    }
+
+    /// Hyperparameters of the multilingual f16 model of the family
+    pub(crate) fn header(&self) -> ModelHeader {
+        let (state, head, layers, n_text_layer, n_mels) = match self {
+            ModelFamily::Tiny => (384, 6, 4, 4, 80),
+            ModelFamily::Base => (512, 8, 6, 6, 80),
+            ModelFamily::Small => (768, 12, 12, 12, 80),
+            ModelFamily::Medium => (1024, 16, 24, 24, 80),
+            ModelFamily::LargeV1 | ModelFamily::LargeV2 => (1280, 20, 32, 32, 80),
+            ModelFamily::LargeV3 => (1280, 20, 32, 32, 128),
+            ModelFamily::LargeV3Turbo => (1280, 20, 32, 4, 128),
+        };
+        ModelHeader {
+            n_vocab: 51865,
+            n_audio_ctx: 1500,
+            n_audio_state: state,
+            n_audio_head: head,
+            n_audio_layer: layers,
+            n_text_ctx: 448,
+            n_text_state: state,
+            n_text_head: head,
+            n_text_layer,
+            n_mels,
+            ftype: 1,
+        }
+    }
 }
 
-/// OpenAI supported models
-#[derive(Default, Clone, Debug, EnumIter, EnumString, Display)]
+/// Storage type of the model weights
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Quantization {
+    F16,
+    Q5_0,
+    Q5_1,
+    Q8_0,
+}
+
+/// Structured metadata of a built-in model
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelInfo {
+    /// The code used to select the model, e.g. `tiny_en-q5_1`
+    pub code: String,
+    /// Human readable name, e.g. `TinyEn`
+    pub name: String,
+    pub family: ModelFamily,
+    pub quantization: Quantization,
+    pub english_only: bool,
+    /// Size of the ggml file in bytes
+    pub download_size: u64,
+    /// Estimated memory needed to run the model in bytes, see [Model::required_memory]
+    pub memory: u64,
+    /// Transcription speed compared to the large models
+    pub relative_speed: f32,
+    /// Translation to English is supported
+    pub translation: bool,
+    /// Speaker turn detection (tinydiarize) is supported
+    pub tdrz: bool,
+}
+
+/// OpenAI supported models, serialized as their [ModelInfo::code] with the `serde` feature
+#[derive(Default, Clone, Debug, PartialEq, Eq, EnumIter, EnumString, Display)]
 #[strum(serialize_all = "snake_case")]
 pub enum Model {
     /// The tiny model.
@@ -34,7 +141,7 @@ pub enum Model {
     #[strum(serialize = "tiny_en-q5_1", to_string = "TinyEn - tiny_en-q5_1")]
     TinyEnQ5_1,
     /// The tiny-q8_0 model with only English support.
-    #[strum(serialize = "tiny_en-q8_0", to_string = "Tiny - tiny_en-q8_0")]
+    #[strum(serialize = "tiny_en-q8_0", to_string = "TinyEn - tiny_en-q8_0")]
     TinyEnQ8_0,
     /// The base model.
     #[default]
@@ -50,7 +157,7 @@ pub enum Model {
     #[strum(serialize = "base_en", to_string = "BaseEn - base_en")]
     BaseEn,
     /// The base-q5_1 model with only English support.
-    #[strum(serialize = "base_en-q5_1", to_string = "BaseEn -base_en-q5_1")]
+    #[strum(serialize = "base_en-q5_1", to_string = "BaseEn - base_en-q5_1")]
     BaseEnQ5_1,
     /// The base-q8_0 model with only English support.
     #[strum(serialize = "base_en-q8_0", to_string = "BaseEn - base_en-q8_0")]
@@ -86,7 +193,7 @@ pub enum Model {
     #[strum(serialize = "medium_en", to_string = "MediumEn - medium_en")]
     MediumEn,
     /// The medium-q5_0 model with only English support.
-    #[strum(serialize = "medium_en-q5_0	", to_string = "MediumEn - medium_en-q5_0")]
+    #[strum(serialize = "medium_en-q5_0", to_string = "MediumEn - medium_en-q5_0")]
     MediumEnQ5_0,
     /// The medium-q8_0 model with only English support.
     #[strum(serialize = "medium_en-q8_0", to_string = "MediumEn - medium_en-q8_0")]
@@ -128,7 +235,7 @@ pub enum Model {
 }
 
 impl Model {
-    fn hf_coordinates(&self) -> HFCoordinates {
+    pub(crate) fn hf_coordinates(&self) -> HFCoordinates {
         let repo = Repo::with_revision(
             "ggerganov/whisper.cpp".to_owned(),
             hf_hub::RepoType::Model,
@@ -270,36 +377,137 @@ impl Model {
         }
     }
 
+    /// Structured metadata of the model. Sizes are rounded, the memory is an estimate.
+    pub fn info(&self) -> ModelInfo {
+        let (family, quantization, size) = match self {
+            Model::Tiny => (ModelFamily::Tiny, Quantization::F16, 78),
+            Model::TinyQ5_1 => (ModelFamily::Tiny, Quantization::Q5_1, 31),
+            Model::TinyQ8_0 => (ModelFamily::Tiny, Quantization::Q8_0, 44),
+            Model::TinyEn => (ModelFamily::Tiny, Quantization::F16, 78),
+            Model::TinyEnQ5_1 => (ModelFamily::Tiny, Quantization::Q5_1, 32),
+            Model::TinyEnQ8_0 => (ModelFamily::Tiny, Quantization::Q8_0, 44),
+            Model::Base => (ModelFamily::Base, Quantization::F16, 148),
+            Model::BaseQ5_1 => (ModelFamily::Base, Quantization::Q5_1, 57),
+            Model::BaseQ8_0 => (ModelFamily::Base, Quantization::Q8_0, 82),
+            Model::BaseEn => (ModelFamily::Base, Quantization::F16, 148),
+            Model::BaseEnQ5_1 => (ModelFamily::Base, Quantization::Q5_1, 57),
+            Model::BaseEnQ8_0 => (ModelFamily::Base, Quantization::Q8_0, 82),
+            Model::Small => (ModelFamily::Small, Quantization::F16, 488),
+            Model::SmallQ5_1 => (ModelFamily::Small, Quantization::Q5_1, 190),
+            Model::SmallQ8_0 => (ModelFamily::Small, Quantization::Q8_0, 264),
+            Model::SmallEn => (ModelFamily::Small, Quantization::F16, 488),
+            Model::SmallEnQ5_1 => (ModelFamily::Small, Quantization::Q5_1, 190),
+            Model::SmallEnQ8_0 => (ModelFamily::Small, Quantization::Q8_0, 264),
+            Model::Medium => (ModelFamily::Medium, Quantization::F16, 1533),
+            Model::MediumQ5_0 => (ModelFamily::Medium, Quantization::Q5_0, 539),
+            Model::MediumQ8_0 => (ModelFamily::Medium, Quantization::Q8_0, 823),
+            Model::MediumEn => (ModelFamily::Medium, Quantization::F16, 1533),
+            Model::MediumEnQ5_0 => (ModelFamily::Medium, Quantization::Q5_0, 539),
+            Model::MediumEnQ8_0 => (ModelFamily::Medium, Quantization::Q8_0, 823),
+            Model::Large => (ModelFamily::LargeV1, Quantization::F16, 3095),
+            Model::LargeV2 => (ModelFamily::LargeV2, Quantization::F16, 3095),
+            Model::LargeV2Q5_0 => (ModelFamily::LargeV2, Quantization::Q5_0, 1080),
+            Model::LargeV2Q8_0 => (ModelFamily::LargeV2, Quantization::Q8_0, 1660),
+            Model::LargeV3 => (ModelFamily::LargeV3, Quantization::F16, 3095),
+            Model::LargeV3Q5_0 => (ModelFamily::LargeV3, Quantization::Q5_0, 1081),
+            Model::LargeV3Turbo => (ModelFamily::LargeV3Turbo, Quantization::F16, 1624),
+            Model::LargeV3TurboQ5_0 => (ModelFamily::LargeV3Turbo, Quantization::Q5_0, 574),
+            Model::LargeV3TurboQ8_0 => (ModelFamily::LargeV3Turbo, Quantization::Q8_0, 874),
+        };
+        let display = self.to_string();
+        let (name, code) = display.split_once(" - ").unwrap();
+        let english_only = !self.is_multilingual();
+        ModelInfo {
+            code: code.to_owned(),
+            name: name.to_owned(),
+            family,
+            quantization,
+            english_only,
+            download_size: size * MB,
+            memory: size * MB + family.header().state_memory(REFERENCE_THREADS),
+            relative_speed: family.relative_speed(),
+            // Turbo was fine-tuned on transcription only
+            translation: !english_only && family != ModelFamily::LargeV3Turbo,
+            tdrz: false,
+        }
+    }
+
+    /// Estimated memory in bytes needed to load the weights and run a transcription on `threads` threads
+    pub fn required_memory(&self, threads: usize) -> u64 {
+        let info = self.info();
+        info.download_size + info.family.header().state_memory(threads)
+    }
+
     /// True if the model supports multiple languages, false otherwise.
     pub fn is_multilingual(&self) -> bool {
-        !self.to_string().contains("en")
+        !matches!(
+            self,
+            Model::TinyEn
+                | Model::TinyEnQ5_1
+                | Model::TinyEnQ8_0
+                | Model::BaseEn
+                | Model::BaseEnQ5_1
+                | Model::BaseEnQ8_0
+                | Model::SmallEn
+                | Model::SmallEnQ5_1
+                | Model::SmallEnQ8_0
+                | Model::MediumEn
+                | Model::MediumEnQ5_0
+                | Model::MediumEnQ8_0
+        )
     }
 
     /// Check if the file model has been cached before
     pub fn cached(&self) -> bool {
+        self.cached_with_config(&DownloadConfig::default())
+    }
+
+    /// Same as [Model::cached] looking in a custom cache, at a custom revision
+    pub fn cached_with_config(&self, config: &DownloadConfig) -> bool {
+        self.cached_path(config).is_some()
+    }
+
+    /// Location of the model file in the cache, if downloaded
+    pub(crate) fn cached_path(&self, config: &DownloadConfig) -> Option<PathBuf> {
         let coordinates = self.hf_coordinates();
-        let cache = Cache::from_env().repo(coordinates.repo);
-        cache.get(&coordinates.model).is_some()
+        let cache = config.cache().repo(config.repo(coordinates.repo));
+        cache.get(&coordinates.model)
     }
 
+    /// SHA-256 of the model file on the `main` revision of the hub, if it has been recorded
+    pub fn sha256(&self) -> Option<&'static str> {
+        MODEL_SHA256
+            .iter()
+            .find(|(model, _)| model == self)
+            .map(|(_, sha256)| *sha256)
+    }
+}
+
+/// Downloads, available with the `tokio` feature
+#[cfg(feature = "tokio")]
+impl Model {
     pub(crate) async fn internal_download_model(
         &self,
         force_download: bool,
-        progress: ProgressType,
+        offline: bool,
+        observer: Arc<dyn DownloadObserver>,
+        config: &DownloadConfig,
     ) -> Result<PathBuf, Error> {
         let coordinates = self.hf_coordinates();
 
         download_file(
             &coordinates.model,
             force_download,
-            progress,
+            offline,
+            observer,
             coordinates.repo,
+            config,
         )
         .await
     }
 
     pub async fn download_model(&self, force_download: bool) -> Result<PathBuf, Error> {
-        self.internal_download_model(force_download, ProgressType::ProgressBar)
+        self.download_model_with_config(force_download, &DownloadConfig::default())
             .await
     }
 
@@ -308,7 +516,112 @@ impl Model {
         force_download: bool,
         tx: UnboundedSender<Event>,
     ) -> Result<PathBuf, Error> {
-        self.internal_download_model(force_download, ProgressType::Callback(tx))
+        self.download_model_listener_with_config(force_download, tx, &DownloadConfig::default())
+            .await
+    }
+
+    /// Same as [Model::download_model] using a custom hub, cache, token or proxy
+    pub async fn download_model_with_config(
+        &self,
+        force_download: bool,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        let observer = Arc::new(ProgressBarObserver::default());
+        self.download_model_with_observer(force_download, observer, config)
+            .await
+    }
+
+    /// Same as [Model::download_model_listener] using a custom hub, cache, token or proxy
+    pub async fn download_model_listener_with_config(
+        &self,
+        force_download: bool,
+        tx: UnboundedSender<Event>,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        let observer = Arc::new(ChannelObserver::new(tx));
+        self.download_model_with_observer(force_download, observer, config)
             .await
     }
+
+    /// Same as [Model::download_model_with_config] reporting the progress to a custom observer
+    pub async fn download_model_with_observer(
+        &self,
+        force_download: bool,
+        observer: Arc<dyn DownloadObserver>,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        self.internal_download_model(force_download, offline_from_env(), observer, config)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn info_catalogue() {
+        for model in Model::iter() {
+            let info = model.info();
+            assert_eq!(Model::from_str(&info.code).unwrap(), model);
+            assert_eq!(info.english_only, info.code.contains("_en"));
+            assert!(info.memory > info.download_size);
+            assert_eq!(info.memory, model.required_memory(REFERENCE_THREADS));
+            assert!(model.required_memory(8) > info.memory);
+        }
+        let info = Model::LargeV3TurboQ5_0.info();
+        assert_eq!(info.name, "Large V3 Turbo");
+        assert_eq!(info.family, ModelFamily::LargeV3Turbo);
+        assert_eq!(info.quantization.to_string(), "q5_0");
+        assert!(!info.translation);
+        assert!(Model::Small.info().translation);
+        assert!(!Model::SmallEn.info().translation);
+    }
+
+    #[test]
+    fn checksum_table() {
+        for (index, (model, sha256)) in MODEL_SHA256.iter().enumerate() {
+            assert_eq!(sha256.len(), 64, "{model:?}");
+            assert!(sha256.bytes().all(|b| b.is_ascii_hexdigit()), "{model:?}");
+            assert!(
+                MODEL_SHA256[..index].iter().all(|(seen, _)| seen != model),
+                "{model:?}"
+            );
+        }
+    }
+
+    /// Compare the table with the LFS pointers of the hub, printing the missing entries
+    #[ignore]
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn published_checksums() {
+        let tree =
+            reqwest::get("https://huggingface.co/api/models/ggerganov/whisper.cpp/tree/main")
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+        let tree: serde_json::Value = serde_json::from_str(&tree).unwrap();
+        let published = |file: &str| {
+            tree.as_array()?
+                .iter()
+                .find(|entry| entry["path"] == file)?["lfs"]["oid"]
+                .as_str()
+                .map(str::to_owned)
+        };
+        let mut missing = String::new();
+        for model in Model::iter() {
+            let sha256 = published(&model.hf_coordinates().model).unwrap();
+            match model.sha256() {
+                Some(recorded) => assert_eq!(recorded, sha256, "{model:?}"),
+                None => missing += &format!("    (Model::{model:?}, \"{sha256}\"),\n"),
+            }
+        }
+        assert!(missing.is_empty(), "missing checksums:\n{missing}");
+    }
 }
@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "tokio")]
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, error, info, warn};
+
+use crate::{Error, Event};
+
+/// Receives the progress of the model downloads.
+///
+/// The same observer may follow several files at once, every call names the file it refers to.
+pub trait DownloadObserver: Send + Sync {
+    /// The transfer of `file` starts, `size` bytes are expected
+    fn init(&self, file: &str, size: u64);
+    /// `delta` more bytes of `file` have been received
+    fn update(&self, file: &str, delta: u64);
+    /// The transfer of `file` has been completed
+    fn finish(&self, file: &str);
+    /// `file` could not be made available
+    fn error(&self, file: &str, error: &Error);
+    /// Notifications outside of the transfer: retries and integrity checks
+    fn event(&self, _event: &Event) {}
+}
+
+impl fmt::Debug for dyn DownloadObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DownloadObserver")
+    }
+}
+
+/// Notify every observer of the list
+impl DownloadObserver for Vec<Arc<dyn DownloadObserver>> {
+    fn init(&self, file: &str, size: u64) {
+        self.iter().for_each(|observer| observer.init(file, size));
+    }
+
+    fn update(&self, file: &str, delta: u64) {
+        self.iter()
+            .for_each(|observer| observer.update(file, delta));
+    }
+
+    fn finish(&self, file: &str) {
+        self.iter().for_each(|observer| observer.finish(file));
+    }
+
+    fn error(&self, file: &str, error: &Error) {
+        self.iter().for_each(|observer| observer.error(file, error));
+    }
+
+    fn event(&self, event: &Event) {
+        self.iter().for_each(|observer| observer.event(event));
+    }
+}
+
+/// Store the state of a download
+#[derive(Debug, Clone)]
+struct DownloadState {
+    start_time: Instant,
+    len: u64,
+    offset: u64,
+}
+
+impl DownloadState {
+    fn new(len: u64) -> DownloadState {
+        DownloadState {
+            start_time: Instant::now(),
+            len,
+            offset: 0,
+        }
+    }
+
+    fn update(&mut self, file: &str, delta: u64) -> Option<Event> {
+        if delta == 0 {
+            return None;
+        }
+
+        self.offset += delta;
+
+        let elapsed_time = Instant::now() - self.start_time;
+
+        let progress = self.offset as f32 / self.len as f32;
+        let progress_100 = progress * 100.;
+
+        let remaining_percentage = 100. - progress_100;
+        let duration_unit = elapsed_time
+            / if progress_100 as u32 == 0 {
+                1
+            } else {
+                progress_100 as u32
+            };
+        let remaining_time = duration_unit * remaining_percentage as u32;
+
+        Some(Event::DownloadProgress {
+            file: file.to_owned(),
+            percentage: progress_100,
+            elapsed_time,
+            remaining_time,
+        })
+    }
+}
+
+/// Turn the progress into [Event]s sent on the channel
+pub struct ChannelObserver {
+    send: Box<dyn Fn(Event) + Send + Sync>,
+    downloads: Mutex<HashMap<String, DownloadState>>,
+}
+
+impl ChannelObserver {
+    /// Send the events on `tx`, failures to send are ignored
+    #[cfg(feature = "tokio")]
+    pub fn new(tx: UnboundedSender<Event>) -> Self {
+        Self::from_fn(move |event| {
+            let _ = tx.send(event);
+        })
+    }
+
+    /// Pass the events to `send`, e.g. to deliver them on a channel of another runtime
+    pub fn from_fn(send: impl Fn(Event) + Send + Sync + 'static) -> Self {
+        Self {
+            send: Box::new(send),
+            downloads: Default::default(),
+        }
+    }
+}
+
+impl fmt::Debug for ChannelObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelObserver")
+            .field("downloads", &self.downloads)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DownloadObserver for ChannelObserver {
+    fn init(&self, file: &str, size: u64) {
+        self.downloads
+            .lock()
+            .unwrap()
+            .insert(file.to_owned(), DownloadState::new(size));
+        (self.send)(Event::DownloadStarted {
+            file: file.to_owned(),
+        });
+    }
+
+    fn update(&self, file: &str, delta: u64) {
+        let update = self
+            .downloads
+            .lock()
+            .unwrap()
+            .get_mut(file)
+            .and_then(|state| state.update(file, delta));
+        if let Some(event) = update {
+            (self.send)(event);
+        }
+    }
+
+    fn finish(&self, file: &str) {
+        self.downloads.lock().unwrap().remove(file);
+        (self.send)(Event::DownloadCompleted {
+            file: file.to_owned(),
+        });
+    }
+
+    /// Failures are returned to the caller, only the state is dropped
+    fn error(&self, file: &str, _error: &Error) {
+        self.downloads.lock().unwrap().remove(file);
+    }
+
+    fn event(&self, event: &Event) {
+        (self.send)(event.clone());
+    }
+}
+
+/// Draw an indicatif progress bar on the terminal
+#[derive(Debug, Default)]
+pub struct ProgressBarObserver {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl DownloadObserver for ProgressBarObserver {
+    fn init(&self, file: &str, size: u64) {
+        let bar = ProgressBar::new(size);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{msg} [{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})",
+            )
+            .unwrap(),
+        );
+        bar.set_message(file.to_owned());
+        *self.bar.lock().unwrap() = Some(bar);
+    }
+
+    fn update(&self, _file: &str, delta: u64) {
+        if let Some(bar) = self.bar.lock().unwrap().as_ref() {
+            bar.inc(delta);
+        }
+    }
+
+    fn finish(&self, _file: &str) {
+        if let Some(bar) = self.bar.lock().unwrap().take() {
+            bar.finish();
+        }
+    }
+
+    fn error(&self, file: &str, error: &Error) {
+        match self.bar.lock().unwrap().take() {
+            Some(bar) => bar.abandon_with_message(format!("{file}: {error}")),
+            None => eprintln!("{file}: {error}"),
+        }
+    }
+
+    fn event(&self, event: &Event) {
+        match self.bar.lock().unwrap().as_ref() {
+            Some(bar) => bar.println(event.to_string()),
+            None => eprintln!("{event}"),
+        }
+    }
+}
+
+/// Log the progress with `tracing`, every tenth of the file or once `interval` has elapsed
+#[derive(Debug)]
+pub struct TracingObserver {
+    interval: Duration,
+    downloads: Mutex<HashMap<String, (DownloadState, Instant)>>,
+}
+
+impl TracingObserver {
+    /// Log the progress at least every `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            downloads: Default::default(),
+        }
+    }
+}
+
+impl Default for TracingObserver {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5))
+    }
+}
+
+impl DownloadObserver for TracingObserver {
+    fn init(&self, file: &str, size: u64) {
+        info!(file, size, "download started");
+        self.downloads
+            .lock()
+            .unwrap()
+            .insert(file.to_owned(), (DownloadState::new(size), Instant::now()));
+    }
+
+    fn update(&self, file: &str, delta: u64) {
+        let mut downloads = self.downloads.lock().unwrap();
+        let Some((state, logged)) = downloads.get_mut(file) else {
+            return;
+        };
+        let decile = |state: &DownloadState| state.offset * 10 / state.len.max(1);
+        let previous = decile(state);
+        if let Some(Event::DownloadProgress {
+            percentage,
+            remaining_time,
+            ..
+        }) = state.update(file, delta)
+            && (decile(state) > previous || logged.elapsed() >= self.interval)
+        {
+            *logged = Instant::now();
+            debug!(file, percentage, ?remaining_time, "downloading");
+        }
+    }
+
+    fn finish(&self, file: &str) {
+        if let Some((state, _)) = self.downloads.lock().unwrap().remove(file) {
+            info!(file, elapsed = ?state.start_time.elapsed(), "download completed");
+        }
+    }
+
+    fn error(&self, file: &str, error: &Error) {
+        self.downloads.lock().unwrap().remove(file);
+        error!(file, %error, "download failed");
+    }
+
+    fn event(&self, event: &Event) {
+        match event {
+            Event::DownloadRetry { .. } => warn!("{event}"),
+            _ => info!("{event}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn channel_fan_out() {
+        let (tx, rx) = channel();
+        let observers: Vec<Arc<dyn DownloadObserver>> = vec![
+            Arc::new(ChannelObserver::from_fn(move |event| {
+                let _ = tx.send(event);
+            })),
+            Arc::new(TracingObserver::default()),
+        ];
+
+        observers.init("ggml.bin", 100);
+        observers.update("ggml.bin", 0);
+        observers.update("ggml.bin", 50);
+        observers.update("other.bin", 50);
+        observers.finish("ggml.bin");
+        drop(observers);
+
+        assert!(rx.try_recv().unwrap().is_download_started());
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            Event::DownloadProgress { file, percentage, .. } if file == "ggml.bin" && percentage == 50.
+        ));
+        assert!(rx.try_recv().unwrap().is_download_completed());
+        assert!(rx.try_recv().is_err());
+    }
+}
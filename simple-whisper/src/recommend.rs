@@ -0,0 +1,306 @@
+use derive_builder::Builder;
+use strum::{Display, EnumString, IntoEnumIterator};
+
+use crate::{Language, Model, ModelFamily, Quantization};
+
+/// Trade-off between transcription speed and accuracy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Preference {
+    Speed,
+    #[default]
+    Balanced,
+    Accuracy,
+}
+
+impl Preference {
+    /// Weights of the accuracy and of the speed in the score
+    fn weights(&self) -> (f32, f32) {
+        match self {
+            Preference::Speed => (0.1, 0.9),
+            Preference::Balanced => (0.5, 0.5),
+            Preference::Accuracy => (0.8, 0.2),
+        }
+    }
+}
+
+/// The hardware and the audio the model is chosen for.
+///
+/// Unset fields describe the current machine: available memory, CPU cores
+/// and the GPU backends enabled at compile time.
+#[derive(Builder, Clone, Debug)]
+#[builder(setter(into, strip_option), default)]
+pub struct Constraints {
+    /// Language of the audio to transcribe
+    language: Language,
+    preference: Preference,
+    /// Memory the model can use in bytes, None if unknown
+    available_memory: Option<u64>,
+    cpu_cores: usize,
+    /// Whisper runs on a GPU backend
+    gpu: bool,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self {
+            language: Language::English,
+            preference: Preference::default(),
+            available_memory: available_memory(),
+            cpu_cores: num_cpus::get(),
+            gpu: cfg!(any(
+                feature = "metal",
+                feature = "vulkan",
+                feature = "cuda",
+                feature = "hipblas"
+            )),
+        }
+    }
+}
+
+/// A model fitting the constraints, with the reasons of its rank
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recommendation {
+    pub model: Model,
+    /// Higher is better, between 0 and 1
+    pub score: f32,
+    pub reasons: Vec<String>,
+}
+
+impl Model {
+    /// Rank the models able to run under the constraints, best first
+    pub fn recommend(constraints: &Constraints) -> Vec<Recommendation> {
+        let (accuracy_weight, speed_weight) = constraints.preference.weights();
+        // Whisper scales up to 8 threads, GPUs are several times faster than any CPU
+        let throughput = if constraints.gpu {
+            4.
+        } else {
+            constraints.cpu_cores.clamp(1, 8) as f32 / 8.
+        };
+
+        let mut recommendations: Vec<Recommendation> = Model::iter()
+            .filter_map(|model| {
+                let info = model.info();
+                let memory = model.required_memory(constraints.cpu_cores);
+                let mut reasons = Vec::new();
+                if info.english_only && !constraints.language.is_english() {
+                    return None;
+                }
+                match constraints.available_memory {
+                    Some(available) if memory > available => return None,
+                    Some(available) => reasons.push(format!(
+                        "needs about {} MB of the {} MB available",
+                        memory / 1_000_000,
+                        available / 1_000_000
+                    )),
+                    None => reasons.push(format!(
+                        "needs about {} MB, available memory unknown",
+                        memory / 1_000_000
+                    )),
+                }
+
+                let mut accuracy = accuracy(info.family);
+                match info.quantization {
+                    Quantization::F16 => {}
+                    Quantization::Q8_0 => {
+                        accuracy -= 0.1;
+                        reasons.push(
+                            "q8_0 weights: smaller with a negligible accuracy loss".to_owned(),
+                        );
+                    }
+                    Quantization::Q5_0 | Quantization::Q5_1 => {
+                        accuracy -= 0.3;
+                        reasons.push(format!(
+                            "{} weights: about a third of the memory, slightly less accurate",
+                            info.quantization
+                        ));
+                    }
+                }
+                // The English only versions are more accurate, mostly on the smaller sizes
+                if info.english_only && matches!(info.family, ModelFamily::Tiny | ModelFamily::Base)
+                {
+                    accuracy += 0.3;
+                    reasons.push("English only, more accurate on English audio".to_owned());
+                } else if !info.english_only && !constraints.language.is_english() {
+                    reasons.push(format!("multilingual, supports {:?}", constraints.language));
+                }
+
+                let speed = (info.relative_speed * throughput).min(10.);
+                reasons.push(if constraints.gpu {
+                    format!("about {}x faster than large on GPU", info.relative_speed)
+                } else {
+                    format!(
+                        "about {}x faster than large, running on {} CPU cores",
+                        info.relative_speed, constraints.cpu_cores
+                    )
+                });
+
+                Some(Recommendation {
+                    model,
+                    score: accuracy_weight * accuracy / MAX_ACCURACY + speed_weight * speed / 10.,
+                    reasons,
+                })
+            })
+            .collect();
+        recommendations.sort_by(|a, b| b.score.total_cmp(&a.score));
+        recommendations
+    }
+}
+
+/// Accuracy of the best family
+const MAX_ACCURACY: f32 = 5.8;
+
+/// Relative accuracy of the families, derived from the word error rates published by OpenAI
+fn accuracy(family: ModelFamily) -> f32 {
+    match family {
+        ModelFamily::Tiny => 1.,
+        ModelFamily::Base => 2.,
+        ModelFamily::Small => 3.,
+        ModelFamily::Medium => 4.,
+        ModelFamily::LargeV1 => 4.5,
+        ModelFamily::LargeV2 => 5.,
+        ModelFamily::LargeV3Turbo => 5.3,
+        ModelFamily::LargeV3 => 5.5,
+    }
+}
+
+/// Memory available to new processes, read from `/proc/meminfo` where present
+#[cfg(not(any(target_os = "macos", windows)))]
+pub(crate) fn available_memory() -> Option<u64> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+/// Memory available to new processes: the free pages and the ones reclaimed without swapping
+#[cfg(target_os = "macos")]
+pub(crate) fn available_memory() -> Option<u64> {
+    let mut stats = std::mem::MaybeUninit::<libc::vm_statistics64>::zeroed();
+    let mut count = libc::HOST_VM_INFO64_COUNT;
+    // SAFETY: `count` is the size of the buffer in integers, as required by the flavor
+    let (result, page_size) = unsafe {
+        #[allow(deprecated)]
+        let host = libc::mach_host_self();
+        let result = libc::host_statistics64(
+            host,
+            libc::HOST_VM_INFO64,
+            stats.as_mut_ptr() as libc::host_info64_t,
+            &mut count,
+        );
+        (result, libc::sysconf(libc::_SC_PAGESIZE))
+    };
+    if result != libc::KERN_SUCCESS || page_size <= 0 {
+        return None;
+    }
+    // SAFETY: filled by the successful call
+    let stats = unsafe { stats.assume_init() };
+    let pages =
+        stats.free_count as u64 + stats.inactive_count as u64 + stats.speculative_count as u64;
+    Some(pages * page_size as u64)
+}
+
+/// Memory available to new processes, as reported by `GlobalMemoryStatusEx`
+#[cfg(windows)]
+pub(crate) fn available_memory() -> Option<u64> {
+    #[repr(C)]
+    struct MemoryStatusEx {
+        length: u32,
+        memory_load: u32,
+        total_phys: u64,
+        avail_phys: u64,
+        total_page_file: u64,
+        avail_page_file: u64,
+        total_virtual: u64,
+        avail_virtual: u64,
+        avail_extended_virtual: u64,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GlobalMemoryStatusEx(buffer: *mut MemoryStatusEx) -> i32;
+    }
+
+    let mut status = MemoryStatusEx {
+        length: size_of::<MemoryStatusEx>() as u32,
+        memory_load: 0,
+        total_phys: 0,
+        avail_phys: 0,
+        total_page_file: 0,
+        avail_page_file: 0,
+        total_virtual: 0,
+        avail_virtual: 0,
+        avail_extended_virtual: 0,
+    };
+    // SAFETY: the length of the structure is set as the call requires
+    let succeeded = unsafe { GlobalMemoryStatusEx(&mut status) } != 0;
+    succeeded.then_some(status.avail_phys)
+}
+
+/// `MemAvailable` of a `/proc/meminfo` content, in bytes
+#[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(|kb| kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recommend_within_constraints() {
+        let constraints = ConstraintsBuilder::default()
+            .language(Language::Italian)
+            .available_memory(1_000_000_000u64)
+            .cpu_cores(4usize)
+            .gpu(false)
+            .build()
+            .unwrap();
+        let recommendations = Model::recommend(&constraints);
+        assert!(!recommendations.is_empty());
+        for recommendation in &recommendations {
+            let info = recommendation.model.info();
+            assert!(!info.english_only);
+            assert!(info.memory <= 1_000_000_000);
+        }
+        assert!(
+            recommendations
+                .windows(2)
+                .all(|pair| pair[0].score >= pair[1].score)
+        );
+    }
+
+    #[test]
+    fn preference_changes_ranking() {
+        let mut builder = ConstraintsBuilder::default();
+        builder
+            .available_memory(16_000_000_000u64)
+            .cpu_cores(8usize)
+            .gpu(false);
+        let fastest = Model::recommend(&builder.preference(Preference::Speed).build().unwrap());
+        let best = Model::recommend(&builder.preference(Preference::Accuracy).build().unwrap());
+        assert_eq!(fastest[0].model.info().family, ModelFamily::Tiny);
+        assert!(matches!(
+            best[0].model.info().family,
+            ModelFamily::LargeV3 | ModelFamily::LargeV3Turbo
+        ));
+
+        let nothing = Model::recommend(&builder.available_memory(1000u64).build().unwrap());
+        assert!(nothing.is_empty());
+    }
+
+    #[test]
+    fn meminfo_available() {
+        let meminfo = "MemTotal:       16318412 kB
+MemFree:         1022816 kB
+MemAvailable:    8163212 kB
+";
+        assert_eq!(parse_meminfo(meminfo), Some(8163212 * 1024));
+        assert_eq!(parse_meminfo("MemTotal:       16318412 kB\n"), None);
+    }
+}
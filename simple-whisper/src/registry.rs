@@ -0,0 +1,202 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{LazyLock, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Model, ModelSource};
+
+/// Environment variable pointing to the manifest loaded by [ModelManifest::from_env]
+pub const MANIFEST_ENV: &str = "SIMPLE_WHISPER_MODELS";
+
+/// Models registered in this process, by name
+static REGISTRY: LazyLock<RwLock<BTreeMap<String, RegisteredModel>>> =
+    LazyLock::new(Default::default);
+
+/// A custom model declared in a manifest, hosted on Hugging Face or stored locally
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredModel {
+    /// The name the model is resolved by, taken from the manifest key
+    #[serde(skip)]
+    pub name: String,
+    /// Hugging Face repository hosting the file
+    pub repo: Option<String>,
+    /// Local ggml file, relative to the manifest
+    pub path: Option<PathBuf>,
+    /// File name inside the repository
+    pub file: Option<String>,
+    #[serde(default = "default_revision")]
+    pub revision: String,
+    /// Expected SHA-256 of the ggml file, checked every time the model is loaded
+    pub sha256: Option<String>,
+    /// Overrides the multilingual support read from the model header
+    pub multilingual: Option<bool>,
+    pub description: Option<String>,
+}
+
+impl RegisteredModel {
+    /// Where the model file is loaded from
+    pub fn source(&self) -> ModelSource {
+        match (&self.repo, &self.file, &self.path) {
+            (Some(repo), Some(file), _) => ModelSource::HuggingFace {
+                repo: repo.clone(),
+                file: file.clone(),
+                revision: self.revision.clone(),
+            },
+            (_, _, path) => ModelSource::Local(path.clone().unwrap_or_default()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if Model::from_str(&self.name).is_ok() || self.name.starts_with("hf:") {
+            return Err("the name is reserved".to_owned());
+        }
+        match (&self.repo, &self.file, &self.path) {
+            (Some(_), Some(_), None) | (None, None, Some(_)) => {}
+            (Some(_), None, None) => return Err("file is required with repo".to_owned()),
+            _ => return Err("either repo and file or path must be set".to_owned()),
+        }
+        if let Some(sha256) = &self.sha256
+            && (sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err("sha256 must be 64 hexadecimal digits".to_owned());
+        }
+        Ok(())
+    }
+}
+
+fn default_revision() -> String {
+    "main".to_owned()
+}
+
+/// A set of named custom models, written in TOML or JSON:
+///
+/// ```toml
+/// [models.whisper-it]
+/// repo = "owner/whisper-it"
+/// file = "ggml-model.bin"
+/// multilingual = true
+///
+/// [models.distil-small]
+/// path = "models/ggml-distil-small.en.bin"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelManifest {
+    #[serde(default)]
+    pub models: BTreeMap<String, RegisteredModel>,
+}
+
+impl ModelManifest {
+    /// Read a manifest, JSON if the extension is `json`, TOML otherwise
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = read_to_string(path)?;
+        let invalid = |reason: String| Error::Manifest(format!("{}: {reason}", path.display()));
+        let mut manifest: Self = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&content).map_err(|err| invalid(err.to_string()))?
+        } else {
+            toml::from_str(&content).map_err(|err| invalid(err.to_string()))?
+        };
+        let base = path.parent().unwrap_or(Path::new(""));
+        for (name, model) in &mut manifest.models {
+            model.name = name.clone();
+            model.path = model.path.as_ref().map(|local| base.join(local));
+            model
+                .validate()
+                .map_err(|reason| invalid(format!("{name}: {reason}")))?;
+        }
+        Ok(manifest)
+    }
+
+    /// Load the manifest pointed by the `SIMPLE_WHISPER_MODELS` environment variable, if set
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        env::var_os(MANIFEST_ENV)
+            .map(|path| Self::load(Path::new(&path)))
+            .transpose()
+    }
+
+    /// Make the models resolvable by name when parsing a [ModelSource]
+    pub fn register(self) {
+        REGISTRY.write().unwrap().extend(self.models);
+    }
+}
+
+/// The models registered in this process
+pub fn registered_models() -> Vec<RegisteredModel> {
+    REGISTRY.read().unwrap().values().cloned().collect()
+}
+
+pub(crate) fn registered_model(name: &str) -> Option<RegisteredModel> {
+    REGISTRY.read().unwrap().get(name).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn register_manifest() {
+        let dir = tempdir().unwrap();
+        let toml = dir.path().join("models.toml");
+        write(
+            &toml,
+            r#"
+[models.whisper-it]
+repo = "owner/whisper-it"
+file = "ggml-model.bin"
+multilingual = true
+description = "Fine-tuned on Italian podcasts"
+
+[models.distil-small]
+path = "ggml-distil-small.en.bin"
+sha256 = "0000000000000000000000000000000000000000000000000000000000000000"
+"#,
+        )
+        .unwrap();
+        let manifest = ModelManifest::load(&toml).unwrap();
+        assert_eq!(manifest.models.len(), 2);
+        manifest.register();
+
+        assert_eq!(
+            registered_model("whisper-it").unwrap().source(),
+            ModelSource::hugging_face("owner/whisper-it", "ggml-model.bin")
+        );
+        let source = ModelSource::from_str("distil-small").unwrap();
+        assert_eq!(source.to_string(), "distil-small");
+        let ModelSource::Registered(registered) = source else {
+            panic!("distil-small is not registered")
+        };
+        assert_eq!(
+            registered.source(),
+            ModelSource::Local(dir.path().join("ggml-distil-small.en.bin"))
+        );
+        // Built-in models are not shadowed
+        assert_eq!(
+            ModelSource::from_str("tiny").unwrap(),
+            ModelSource::Model(Model::Tiny)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_manifest() {
+        let dir = tempdir().unwrap();
+        let json = dir.path().join("models.json");
+        write(&json, r#"{"models": {"tiny": {"path": "ggml-tiny.bin"}}}"#).unwrap();
+        assert!(matches!(
+            ModelManifest::load(&json),
+            Err(Error::Manifest(_))
+        ));
+        write(&json, r#"{"models": {"custom": {"repo": "owner/repo"}}}"#).unwrap();
+        assert!(ModelManifest::load(&json).is_err());
+        write(&json, r#"{"models": {"custom": {"path": "model.bin"}}}"#).unwrap();
+        assert!(ModelManifest::load(&json).is_ok());
+    }
+}
@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+use crate::{Language, Model};
+
+/// Durations as fractional seconds
+pub(crate) mod seconds {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Language {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Language {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Language::from_str(&code)
+            .map_err(|_| D::Error::custom(format!("unsupported language {code}")))
+    }
+}
+
+impl Serialize for Model {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.info().code)
+    }
+}
+
+impl<'de> Deserialize<'de> for Model {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        Model::from_str(&id).map_err(|_| D::Error::custom(format!("unsupported model {id}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::{Event, Language, Model};
+
+    #[test]
+    fn language_and_model_ids() {
+        assert_eq!(
+            serde_json::to_value(Language::Italian).unwrap(),
+            json!("it")
+        );
+        assert_eq!(
+            serde_json::to_value(Model::TinyEn).unwrap(),
+            json!("tiny_en")
+        );
+        let model: Model = serde_json::from_value(json!("tiny_en")).unwrap();
+        assert_eq!(model, Model::TinyEn);
+        assert!(serde_json::from_value::<Language>(json!("xx")).is_err());
+        assert!(serde_json::from_value::<Model>(json!("tiny_xx")).is_err());
+    }
+
+    #[test]
+    fn event_round_trip() {
+        let event = Event::Segment {
+            start_offset: Duration::ZERO,
+            end_offset: Duration::from_millis(2500),
+            percentage: 0.25,
+            transcription: "Hello".into(),
+        };
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "event": "segment",
+                "start_offset": 0.0,
+                "end_offset": 2.5,
+                "percentage": 0.25,
+                "transcription": "Hello",
+            })
+        );
+        let Event::Segment { end_offset, .. } = serde_json::from_value(value).unwrap() else {
+            panic!("not a segment")
+        };
+        assert_eq!(end_offset, Duration::from_millis(2500));
+
+        let retry: Event = serde_json::from_value(json!({
+            "event": "download_retry",
+            "file": "ggml-tiny.bin",
+            "attempt": 2,
+            "delay": 1.5,
+            "reason": "timeout",
+        }))
+        .unwrap();
+        assert!(
+            matches!(retry, Event::DownloadRetry { delay, .. } if delay == Duration::from_millis(1500))
+        );
+    }
+}
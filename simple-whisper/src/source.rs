@@ -0,0 +1,462 @@
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use hf_hub::{Repo, RepoType};
+#[cfg(feature = "tokio")]
+use tokio::{sync::mpsc::UnboundedSender, task::spawn_blocking};
+
+#[cfg(feature = "tokio")]
+use crate::{
+    ChannelObserver, DownloadObserver, Event, ProgressBarObserver, download::offline_from_env,
+    hub::download_file,
+};
+use crate::{
+    DownloadConfig, Error, Model, ModelHeader, RegisteredModel,
+    download::{remove_cached, sha256_file},
+    registry::registered_model,
+};
+#[cfg(not(feature = "tokio"))]
+use crate::{DownloadObserver, download::blocking_file};
+
+/// Where the ggml model file is loaded from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModelSource {
+    /// One of the models published by whisper.cpp
+    Model(Model),
+    /// A ggml model file on the local file system
+    Local(PathBuf),
+    /// A ggml model file hosted on an arbitrary Hugging Face repository
+    HuggingFace {
+        repo: String,
+        file: String,
+        revision: String,
+    },
+    /// A custom model declared in a [crate::ModelManifest]
+    Registered(RegisteredModel),
+}
+
+impl ModelSource {
+    /// A model file hosted on the main branch of a Hugging Face repository
+    pub fn hugging_face(repo: impl Into<String>, file: impl Into<String>) -> Self {
+        ModelSource::HuggingFace {
+            repo: repo.into(),
+            file: file.into(),
+            revision: "main".to_owned(),
+        }
+    }
+
+    /// True if the model supports multiple languages, false if it is English only.
+    ///
+    /// Custom models are inspected only if they are available locally, None is returned otherwise.
+    pub fn is_multilingual(&self) -> Option<bool> {
+        self.is_multilingual_with_config(&DownloadConfig::default())
+    }
+
+    /// Same as [ModelSource::is_multilingual] looking in a custom cache, at a custom revision
+    pub fn is_multilingual_with_config(&self, config: &DownloadConfig) -> Option<bool> {
+        let header = match self {
+            ModelSource::Model(model) => return Some(model.is_multilingual()),
+            ModelSource::Local(path) => ModelHeader::read(path).ok(),
+            ModelSource::HuggingFace { .. } => self
+                .cached_path(config)
+                .and_then(|path| ModelHeader::read(&path).ok()),
+            ModelSource::Registered(registered) => {
+                return registered
+                    .multilingual
+                    .or_else(|| registered.source().is_multilingual_with_config(config));
+            }
+        };
+        header.map(|header| header.is_multilingual())
+    }
+
+    /// Check if the model file is available without downloading it
+    pub fn cached(&self) -> bool {
+        self.cached_with_config(&DownloadConfig::default())
+    }
+
+    /// Same as [ModelSource::cached] looking in a custom cache, at a custom revision
+    pub fn cached_with_config(&self, config: &DownloadConfig) -> bool {
+        match self {
+            ModelSource::Model(model) => model.cached_with_config(config),
+            ModelSource::Local(path) => path.is_file(),
+            ModelSource::HuggingFace { .. } => self.cached_path(config).is_some(),
+            ModelSource::Registered(registered) => registered.source().cached_with_config(config),
+        }
+    }
+
+    fn cached_path(&self, config: &DownloadConfig) -> Option<PathBuf> {
+        match self {
+            ModelSource::HuggingFace {
+                repo,
+                file,
+                revision,
+            } => config
+                .cache()
+                .repo(config.repo(Repo::with_revision(
+                    repo.clone(),
+                    RepoType::Model,
+                    revision.clone(),
+                )))
+                .get(file),
+            _ => None,
+        }
+    }
+}
+
+/// Downloads, available with the `tokio` feature
+#[cfg(feature = "tokio")]
+impl ModelSource {
+    pub(crate) async fn internal_download_model(
+        &self,
+        force_download: bool,
+        offline: bool,
+        observer: Arc<dyn DownloadObserver>,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        match self {
+            ModelSource::Model(model) => {
+                model
+                    .internal_download_model(force_download, offline, observer, config)
+                    .await
+            }
+            ModelSource::Local(path) => local_file(path),
+            ModelSource::HuggingFace {
+                repo,
+                file,
+                revision,
+            } => {
+                let repo = Repo::with_revision(repo.clone(), RepoType::Model, revision.clone());
+                download_file(file, force_download, offline, observer, repo, config).await
+            }
+            ModelSource::Registered(registered) => {
+                let source = registered.source();
+                let path = Box::pin(source.internal_download_model(
+                    force_download,
+                    offline,
+                    observer,
+                    config,
+                ))
+                .await?;
+                if let Some(expected) = registered.sha256.clone() {
+                    let checked = path.clone();
+                    spawn_blocking(move || verify_registered(&source, &checked, &expected))
+                        .await
+                        .map_err(io::Error::other)??;
+                }
+                Ok(path)
+            }
+        }
+    }
+
+    /// Make the model file available locally, returning its path
+    pub async fn download_model(&self, force_download: bool) -> Result<PathBuf, Error> {
+        self.download_model_with_config(force_download, &DownloadConfig::default())
+            .await
+    }
+
+    /// Make the model file available locally, reporting the progress on the channel
+    pub async fn download_model_listener(
+        &self,
+        force_download: bool,
+        tx: UnboundedSender<Event>,
+    ) -> Result<PathBuf, Error> {
+        self.download_model_listener_with_config(force_download, tx, &DownloadConfig::default())
+            .await
+    }
+
+    /// Same as [ModelSource::download_model] using a custom hub, cache, token or proxy
+    pub async fn download_model_with_config(
+        &self,
+        force_download: bool,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        let observer = Arc::new(ProgressBarObserver::default());
+        self.download_model_with_observer(force_download, observer, config)
+            .await
+    }
+
+    /// Same as [ModelSource::download_model_listener] using a custom hub, cache, token or proxy
+    pub async fn download_model_listener_with_config(
+        &self,
+        force_download: bool,
+        tx: UnboundedSender<Event>,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        let observer = Arc::new(ChannelObserver::new(tx));
+        self.download_model_with_observer(force_download, observer, config)
+            .await
+    }
+
+    /// Same as [ModelSource::download_model_with_config] reporting the progress to a custom observer
+    pub async fn download_model_with_observer(
+        &self,
+        force_download: bool,
+        observer: Arc<dyn DownloadObserver>,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        self.internal_download_model(force_download, offline_from_env(), observer, config)
+            .await
+    }
+}
+
+/// Blocking downloads, used when the `tokio` feature is disabled
+#[cfg(not(feature = "tokio"))]
+impl ModelSource {
+    /// Path of the model file, downloaded with the `ureq` feature or taken from the cache
+    pub(crate) fn blocking_download_model(
+        &self,
+        force_download: bool,
+        offline: bool,
+        observer: &dyn DownloadObserver,
+        config: &DownloadConfig,
+    ) -> Result<PathBuf, Error> {
+        match self {
+            ModelSource::Model(model) => {
+                let coordinates = model.hf_coordinates();
+                blocking_file(
+                    &coordinates.model,
+                    force_download,
+                    offline,
+                    observer,
+                    coordinates.repo,
+                    config,
+                )
+            }
+            ModelSource::Local(path) => local_file(path),
+            ModelSource::HuggingFace {
+                repo,
+                file,
+                revision,
+            } => {
+                let repo = Repo::with_revision(repo.clone(), RepoType::Model, revision.clone());
+                blocking_file(file, force_download, offline, observer, repo, config)
+            }
+            ModelSource::Registered(registered) => {
+                let source = registered.source();
+                let path =
+                    source.blocking_download_model(force_download, offline, observer, config)?;
+                if let Some(expected) = &registered.sha256 {
+                    verify_registered(&source, &path, expected)?;
+                }
+                Ok(path)
+            }
+        }
+    }
+}
+
+fn local_file(path: &Path) -> Result<PathBuf, Error> {
+    if path.is_file() {
+        Ok(path.to_owned())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("model file {} not found", path.display()),
+        )
+        .into())
+    }
+}
+
+/// Check the file of a registered model against the SHA-256 declared in the manifest.
+///
+/// A corrupted file is removed from the cache, local files are left untouched.
+fn verify_registered(source: &ModelSource, path: &Path, expected: &str) -> Result<(), Error> {
+    let verified = verify_sha256(path, expected);
+    if let Err(Error::ChecksumMismatch { .. }) = &verified
+        && !matches!(source, ModelSource::Local(_))
+    {
+        remove_cached(path)?;
+    }
+    verified
+}
+
+/// Check the model file against the SHA-256 declared in the manifest
+fn verify_sha256(path: &Path, expected: &str) -> Result<(), Error> {
+    let found = sha256_file(path)?;
+    if found.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(Error::ChecksumMismatch {
+            file: path.display().to_string(),
+            expected: expected.to_owned(),
+            found,
+        })
+    }
+}
+
+impl Default for ModelSource {
+    fn default() -> Self {
+        Model::default().into()
+    }
+}
+
+impl From<Model> for ModelSource {
+    fn from(value: Model) -> Self {
+        ModelSource::Model(value)
+    }
+}
+
+impl From<PathBuf> for ModelSource {
+    fn from(value: PathBuf) -> Self {
+        ModelSource::Local(value)
+    }
+}
+
+impl fmt::Display for ModelSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelSource::Model(model) => write!(f, "{model}"),
+            ModelSource::Local(path) => write!(f, "{}", path.display()),
+            ModelSource::HuggingFace {
+                repo,
+                file,
+                revision,
+            } => write!(f, "hf:{repo}/{file}@{revision}"),
+            ModelSource::Registered(registered) => write!(f, "{}", registered.name),
+        }
+    }
+}
+
+/// Parse a model source:
+/// - the code of a built-in model, e.g. `tiny_en`
+/// - the name of a model registered through a [crate::ModelManifest]
+/// - `hf:<owner>/<repo>/<file>[@<revision>]` for a file hosted on Hugging Face
+/// - any other value is a path to a local ggml file
+impl FromStr for ModelSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(model) = Model::from_str(s) {
+            return Ok(model.into());
+        }
+        if let Some(registered) = registered_model(s) {
+            return Ok(ModelSource::Registered(registered));
+        }
+        let Some(coordinates) = s.strip_prefix("hf:") else {
+            return Ok(ModelSource::Local(s.into()));
+        };
+        let (coordinates, revision) = coordinates
+            .rsplit_once('@')
+            .unwrap_or((coordinates, "main"));
+        let mut parts = coordinates.splitn(3, '/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(owner), Some(name), Some(file))
+                if !owner.is_empty() && !name.is_empty() && !file.is_empty() =>
+            {
+                Ok(ModelSource::HuggingFace {
+                    repo: format!("{owner}/{name}"),
+                    file: file.to_owned(),
+                    revision: revision.to_owned(),
+                })
+            }
+            _ => Err(Error::ModelSource(s.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        DownloadConfigBuilder,
+        download::{hex, repo_id, tests::store},
+        header::tests::ggml_file,
+    };
+
+    #[test]
+    fn parse_sources() {
+        assert_eq!(
+            ModelSource::from_str("tiny_en").unwrap(),
+            ModelSource::Model(Model::TinyEn)
+        );
+        assert_eq!(
+            ModelSource::from_str("/models/ggml-custom.bin").unwrap(),
+            ModelSource::Local("/models/ggml-custom.bin".into())
+        );
+        assert_eq!(
+            ModelSource::from_str("hf:owner/whisper-it/ggml-model.bin").unwrap(),
+            ModelSource::hugging_face("owner/whisper-it", "ggml-model.bin")
+        );
+        assert_eq!(
+            ModelSource::from_str("hf:owner/whisper-it/q5/ggml-model.bin@v2").unwrap(),
+            ModelSource::HuggingFace {
+                repo: "owner/whisper-it".to_owned(),
+                file: "q5/ggml-model.bin".to_owned(),
+                revision: "v2".to_owned()
+            }
+        );
+        assert!(ModelSource::from_str("hf:owner/ggml-model.bin").is_err());
+    }
+
+    #[test]
+    fn multilingual_from_header() {
+        let multilingual = ggml_file(51865, 1);
+        let english = ggml_file(51864, 1);
+        assert_eq!(
+            ModelSource::Local(multilingual.path().into()).is_multilingual(),
+            Some(true)
+        );
+        assert_eq!(
+            ModelSource::Local(english.path().into()).is_multilingual(),
+            Some(false)
+        );
+        assert_eq!(
+            ModelSource::Local("missing.bin".into()).is_multilingual(),
+            None
+        );
+    }
+
+    #[test]
+    fn custom_cache() {
+        let dir = tempdir().unwrap();
+        let english = std::fs::read(ggml_file(51864, 1).path()).unwrap();
+        store(dir.path(), &Model::TinyEn, &english);
+        let config = DownloadConfigBuilder::default()
+            .cache_dir(dir.path())
+            .build()
+            .unwrap();
+        let coordinates = Model::TinyEn.hf_coordinates();
+        let source = ModelSource::hugging_face(repo_id(&coordinates.repo), coordinates.model);
+        assert!(source.cached_with_config(&config));
+        assert_eq!(source.is_multilingual_with_config(&config), Some(false));
+        assert!(Model::TinyEn.cached_with_config(&config));
+        assert!(!Model::Base.cached_with_config(&config));
+    }
+
+    #[test]
+    fn registered_checksum() {
+        let local = ggml_file(51865, 1);
+        let source = ModelSource::Local(local.path().into());
+        let sha256 = sha256_file(local.path()).unwrap();
+        assert!(verify_registered(&source, local.path(), &sha256.to_uppercase()).is_ok());
+        let error = verify_registered(&source, local.path(), &"0".repeat(64)).unwrap_err();
+        assert!(matches!(error, Error::ChecksumMismatch { .. }));
+        // Local files are not owned by the cache
+        assert!(local.path().exists());
+
+        let dir = tempdir().unwrap();
+        store(dir.path(), &Model::TinyEn, b"tiny weights");
+        let config = DownloadConfigBuilder::default()
+            .cache_dir(dir.path())
+            .build()
+            .unwrap();
+        let cached = Model::TinyEn.cached_path(&config).unwrap();
+        let source = ModelSource::Model(Model::TinyEn);
+        let error = verify_registered(&source, &cached, &"0".repeat(64)).unwrap_err();
+        assert!(matches!(error, Error::ChecksumMismatch { .. }));
+        // The corrupted file is removed together with its blob
+        assert!(Model::TinyEn.cached_path(&config).is_none());
+        let blobs = dir
+            .path()
+            .join(Model::TinyEn.hf_coordinates().repo.folder_name())
+            .join("blobs");
+        assert!(!blobs.join(hex(&Sha256::digest(b"tiny weights"))).exists());
+    }
+}
@@ -0,0 +1,380 @@
+use std::{fs::read_to_string, path::Path, time::Duration};
+
+use strum::{Display, EnumIter, EnumString};
+
+use crate::{
+    Error, ExportFormat, Segment,
+    align::{align_words, normalize, segment_words, split_words},
+};
+
+/// Supported caption file formats
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum SubtitleFormat {
+    /// SubRip (.srt)
+    Srt,
+    /// WebVTT (.vtt)
+    Vtt,
+}
+
+impl SubtitleFormat {
+    /// Guess the format from the file extension
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "srt" => Some(SubtitleFormat::Srt),
+            "vtt" | "webvtt" => Some(SubtitleFormat::Vtt),
+            _ => None,
+        }
+    }
+
+    /// Parse captions into segments
+    pub fn parse(&self, input: &str) -> Result<Vec<Segment>, Error> {
+        let input = input.trim_start_matches('\u{feff}');
+        match self {
+            SubtitleFormat::Srt => parse_srt(input),
+            SubtitleFormat::Vtt => parse_vtt(input),
+        }
+    }
+
+    /// Read and parse a caption file, the format is detected from the file extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Segment>, Error> {
+        let path = path.as_ref();
+        let format = Self::from_path(path).ok_or_else(|| Error::Subtitle {
+            line: 0,
+            reason: format!("unknown subtitle format for {}", path.display()),
+        })?;
+        format.parse(&read_to_string(path)?)
+    }
+}
+
+impl From<SubtitleFormat> for ExportFormat {
+    fn from(value: SubtitleFormat) -> Self {
+        match value {
+            SubtitleFormat::Srt => ExportFormat::Srt,
+            SubtitleFormat::Vtt => ExportFormat::Vtt,
+        }
+    }
+}
+
+/// Move every caption onto the timing of a fresh transcript of the same audio.
+///
+/// Caption words are aligned to the transcript words, each caption then spans from
+/// its first to its last aligned word. Captions without any aligned word keep their
+/// duration and are shifted by the drift of the closest retimed caption.
+pub fn retime(captions: &[Segment], transcript: &[Segment]) -> Vec<Segment> {
+    let hypothesis = segment_words(transcript);
+    let hypothesis_words: Vec<String> = hypothesis.iter().map(|w| normalize(&w.text)).collect();
+
+    let mut owners = Vec::new();
+    let mut reference = Vec::new();
+    for (idx, caption) in captions.iter().enumerate() {
+        for word in split_words(&caption.transcription) {
+            let word = normalize(word);
+            if !word.is_empty() {
+                owners.push(idx);
+                reference.push(word);
+            }
+        }
+    }
+
+    let mut spans: Vec<Option<(usize, usize)>> = vec![None; captions.len()];
+    for (owner, matched) in owners
+        .iter()
+        .zip(align_words(&reference, &hypothesis_words))
+    {
+        if let Some(idx) = matched.index() {
+            let span = spans[*owner].get_or_insert((idx, idx));
+            span.0 = span.0.min(idx);
+            span.1 = span.1.max(idx);
+        }
+    }
+
+    let drifts: Vec<Option<(Duration, bool)>> = captions
+        .iter()
+        .zip(&spans)
+        .map(|(caption, span)| {
+            span.map(|(first, _)| {
+                let start = hypothesis[first].start_offset;
+                if start >= caption.start_offset {
+                    (start - caption.start_offset, true)
+                } else {
+                    (caption.start_offset - start, false)
+                }
+            })
+        })
+        .collect();
+
+    captions
+        .iter()
+        .enumerate()
+        .map(|(idx, caption)| {
+            let (start_offset, end_offset) = match spans[idx] {
+                Some((first, last)) => {
+                    (hypothesis[first].start_offset, hypothesis[last].end_offset)
+                }
+                None => {
+                    // The retimed caption starting closest in time, the earlier one on ties
+                    let drift = captions
+                        .iter()
+                        .zip(&drifts)
+                        .filter_map(|(other, drift)| drift.map(|drift| (other, drift)))
+                        .min_by_key(|(other, _)| other.start_offset.abs_diff(caption.start_offset))
+                        .map(|(_, drift)| drift);
+                    match drift {
+                        Some((drift, true)) => {
+                            (caption.start_offset + drift, caption.end_offset + drift)
+                        }
+                        Some((drift, false)) => (
+                            caption.start_offset.saturating_sub(drift),
+                            caption.end_offset.saturating_sub(drift),
+                        ),
+                        None => (caption.start_offset, caption.end_offset),
+                    }
+                }
+            };
+            Segment {
+                start_offset,
+                end_offset: end_offset.max(start_offset),
+                transcription: caption.transcription.clone(),
+                speaker: caption.speaker.clone(),
+            }
+        })
+        .collect()
+}
+
+fn parse_srt(input: &str) -> Result<Vec<Segment>, Error> {
+    let mut segments = Vec::new();
+    let mut lines = input.lines().enumerate().peekable();
+    while let Some((idx, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // The numeric counter is optional in the wild
+        let (idx, timing) = if line.contains("-->") {
+            (idx, line)
+        } else {
+            match lines.next() {
+                Some((idx, timing)) => (idx, timing.trim()),
+                None => return Err(subtitle_error(idx, "missing cue timing")),
+            }
+        };
+        let (start_offset, end_offset) = parse_timing(timing, idx)?;
+        let mut text = Vec::new();
+        while let Some((_, line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+            text.push(line.trim());
+        }
+        segments.push(Segment {
+            start_offset,
+            end_offset,
+            transcription: text.join("\n"),
+            speaker: None,
+        });
+    }
+    Ok(segments)
+}
+
+fn parse_vtt(input: &str) -> Result<Vec<Segment>, Error> {
+    let mut lines = input.lines().enumerate().peekable();
+    match lines.next() {
+        Some((_, header)) if header.starts_with("WEBVTT") => {}
+        _ => return Err(subtitle_error(0, "missing WEBVTT header")),
+    }
+    // Header metadata, e.g. `Language: en`, up to the first blank line
+    while lines.next_if(|(_, l)| !l.trim().is_empty()).is_some() {}
+
+    let mut segments = Vec::new();
+    while let Some((idx, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with("NOTE") || line == "STYLE" || line == "REGION" {
+            while lines.next_if(|(_, l)| !l.trim().is_empty()).is_some() {}
+            continue;
+        }
+        // Skip the optional cue identifier
+        let (idx, timing) = if line.contains("-->") {
+            (idx, line)
+        } else {
+            match lines.next() {
+                Some((idx, timing)) => (idx, timing.trim()),
+                None => return Err(subtitle_error(idx, "missing cue timing")),
+            }
+        };
+        let (start_offset, end_offset) = parse_timing(timing, idx)?;
+        let mut speaker = None;
+        let mut text = Vec::new();
+        while let Some((_, line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+            let (voice, line) = strip_vtt_markup(line.trim());
+            speaker = speaker.or(voice);
+            text.push(line);
+        }
+        segments.push(Segment {
+            start_offset,
+            end_offset,
+            transcription: text.join("\n"),
+            speaker,
+        });
+    }
+    Ok(segments)
+}
+
+/// Parse a `start --> end [settings]` line
+fn parse_timing(line: &str, idx: usize) -> Result<(Duration, Duration), Error> {
+    let (start, end) = line
+        .split_once("-->")
+        .ok_or_else(|| subtitle_error(idx, "missing cue timing"))?;
+    let end = end.split_whitespace().next().unwrap_or_default();
+    let start = parse_timestamp(start.trim())
+        .ok_or_else(|| subtitle_error(idx, &format!("invalid timestamp {}", start.trim())))?;
+    let end = parse_timestamp(end)
+        .ok_or_else(|| subtitle_error(idx, &format!("invalid timestamp {end}")))?;
+    Ok((start, end))
+}
+
+/// Parse `[HH:]MM:SS(,|.)mmm`
+fn parse_timestamp(value: &str) -> Option<Duration> {
+    let (clock, millis) = value.rsplit_once([',', '.'])?;
+    let millis: u64 = match millis.len() {
+        1..=3 => millis.parse::<u64>().ok()? * 10u64.pow(3 - millis.len() as u32),
+        _ => return None,
+    };
+    let mut secs = 0;
+    let parts: Vec<&str> = clock.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+    for part in parts {
+        secs = secs * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(Duration::from_millis(secs * 1000 + millis))
+}
+
+/// Drop WebVTT tags and entities returning the voice (`<v Name>`) if any
+fn strip_vtt_markup(line: &str) -> (Option<String>, String) {
+    let mut voice = None;
+    let mut text = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(open) = rest.find('<') {
+        text.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('>') else {
+            rest = &rest[open..];
+            break;
+        };
+        let tag = &rest[open + 1..open + close];
+        if let Some(name) = tag.strip_prefix('v').filter(|n| n.starts_with([' ', '.'])) {
+            let name = name.split_once(' ').map(|(_, n)| n).unwrap_or_default();
+            if !name.trim().is_empty() {
+                voice = Some(name.trim().to_owned());
+            }
+        }
+        rest = &rest[open + close + 1..];
+    }
+    text.push_str(rest);
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&lrm;", "\u{200e}")
+        .replace("&rlm;", "\u{200f}")
+        .replace("&amp;", "&");
+    (voice, text)
+}
+
+fn subtitle_error(idx: usize, reason: &str) -> Error {
+    Error::Subtitle {
+        line: idx + 1,
+        reason: reason.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nAnd so, my fellow Americans,\r\n\r\n2\r\n00:00:03,000 --> 00:00:05,000\r\nask not what your country\r\ncan do for you\r\n";
+
+    const VTT: &str = "WEBVTT - jfk\n\nNOTE drifted\nby two seconds\n\nintro\n00:01.000 --> 00:02.500 align:start\n<v John F. Kennedy>And so, <i>my</i> fellow Americans,\n\n00:00:03.000 --> 00:00:05.000\nask not &amp; what\n";
+
+    #[test]
+    fn srt_parsing() {
+        let segments = SubtitleFormat::Srt.parse(SRT).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].start_offset, Duration::from_millis(1000));
+        assert_eq!(segments[0].end_offset, Duration::from_millis(2500));
+        assert_eq!(
+            segments[1].transcription,
+            "ask not what your country\ncan do for you"
+        );
+    }
+
+    #[test]
+    fn vtt_parsing() {
+        let segments = SubtitleFormat::Vtt.parse(VTT).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].speaker.as_deref(), Some("John F. Kennedy"));
+        assert_eq!(segments[0].transcription, "And so, my fellow Americans,");
+        assert_eq!(segments[1].start_offset, Duration::from_secs(3));
+        assert_eq!(segments[1].transcription, "ask not & what");
+
+        let segments = SubtitleFormat::Vtt
+            .parse("WEBVTT\nLanguage: he\n\n00:00.000 --> 00:01.000\nshalom\n")
+            .unwrap();
+        assert_eq!(segments[0].transcription, "shalom");
+    }
+
+    #[test]
+    fn invalid_timestamp() {
+        let error = SubtitleFormat::Srt
+            .parse("1\n00:00:01 --> 00:00:02,000\ntext\n")
+            .unwrap_err();
+        assert!(matches!(error, Error::Subtitle { line: 2, .. }));
+    }
+
+    #[test]
+    fn retime_drifted_captions() {
+        let captions = SubtitleFormat::Srt.parse(SRT).unwrap();
+        let transcript = vec![
+            Segment {
+                start_offset: Duration::from_secs(3),
+                end_offset: Duration::from_secs(5),
+                transcription: " And so my fellow Americans".to_owned(),
+                speaker: None,
+            },
+            Segment {
+                start_offset: Duration::from_secs(6),
+                end_offset: Duration::from_secs(8),
+                transcription: " ask not what your country can do for you".to_owned(),
+                speaker: None,
+            },
+        ];
+        let retimed = retime(&captions, &transcript);
+        assert_eq!(retimed[0].start_offset, Duration::from_secs(3));
+        assert_eq!(retimed[0].end_offset, Duration::from_secs(5));
+        assert_eq!(retimed[1].start_offset, Duration::from_secs(6));
+        assert_eq!(retimed[1].end_offset, Duration::from_secs(8));
+        assert_eq!(retimed[1].transcription, captions[1].transcription);
+    }
+
+    #[test]
+    fn retime_unaligned_caption_follows_nearest() {
+        let caption = |start: u64, text: &str| Segment {
+            start_offset: Duration::from_secs(start),
+            end_offset: Duration::from_secs(start + 1),
+            transcription: text.to_owned(),
+            speaker: None,
+        };
+        let captions = vec![
+            caption(0, "hello there"),
+            caption(18, "zzz"),
+            caption(20, "goodbye now"),
+        ];
+        let transcript = vec![caption(2, "hello there"), caption(25, "goodbye now")];
+        let retimed = retime(&captions, &transcript);
+        // Shifted like the caption at 20s, not the preceding one at 0s
+        assert_eq!(retimed[1].start_offset, Duration::from_secs(23));
+        assert_eq!(retimed[1].end_offset, Duration::from_secs(24));
+    }
+}
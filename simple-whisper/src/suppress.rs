@@ -0,0 +1,180 @@
+use std::{
+    ffi::{c_int, c_void},
+    slice,
+};
+
+use whisper_rs::{FullParams, WhisperContext, WhisperTokenData, WhisperTokenId};
+
+use crate::transcribe::TranscribeBuilderError;
+
+/// Output the decoder must not produce
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Suppress {
+    /// Text resolved through the model vocabulary, with and without a leading space.
+    /// The decoder can not complete its tokens, e.g. `"♪"` or `"Thanks for watching!"`
+    Text(String),
+    /// Token of the model vocabulary
+    Token(WhisperTokenId),
+}
+
+impl From<&str> for Suppress {
+    fn from(value: &str) -> Self {
+        Suppress::Text(value.to_owned())
+    }
+}
+
+impl From<String> for Suppress {
+    fn from(value: String) -> Self {
+        Suppress::Text(value)
+    }
+}
+
+impl From<WhisperTokenId> for Suppress {
+    fn from(value: WhisperTokenId) -> Self {
+        Suppress::Token(value)
+    }
+}
+
+/// Token sequences banned while decoding
+#[derive(Debug, Default)]
+pub(crate) struct SuppressedTokens {
+    sequences: Vec<Vec<WhisperTokenId>>,
+    n_vocab: usize,
+}
+
+impl SuppressedTokens {
+    /// Resolve `suppress` through the vocabulary of the model
+    pub(crate) fn resolve(
+        context: &WhisperContext,
+        suppress: &[Suppress],
+    ) -> Result<Self, TranscribeBuilderError> {
+        let n_vocab = context.n_vocab();
+        let mut sequences = Vec::new();
+        for entry in suppress {
+            match entry {
+                Suppress::Token(token) if (0..n_vocab).contains(token) => {
+                    sequences.push(vec![*token])
+                }
+                Suppress::Token(token) => return Err(TranscribeBuilderError::UnknownToken(*token)),
+                Suppress::Text(text) => {
+                    for text in [text.clone(), format!(" {text}")] {
+                        let tokens = context.tokenize(&text, text.len() + 1)?;
+                        if !tokens.is_empty() {
+                            sequences.push(tokens);
+                        }
+                    }
+                }
+            }
+        }
+        sequences.sort();
+        sequences.dedup();
+        Ok(Self {
+            sequences,
+            n_vocab: n_vocab as usize,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+
+    /// Tokens completing a banned sequence after `generated`
+    fn banned<'a>(
+        &'a self,
+        generated: &'a [WhisperTokenId],
+    ) -> impl Iterator<Item = WhisperTokenId> + 'a {
+        self.sequences.iter().filter_map(|sequence| {
+            let (last, prefix) = sequence.split_last()?;
+            generated.ends_with(prefix).then_some(*last)
+        })
+    }
+
+    /// Install the logits filter on `params`.
+    ///
+    /// The tokens are read by whisper.cpp while decoding: they must not be moved or
+    /// dropped before the inference using `params` has completed.
+    pub(crate) fn install(&self, params: &mut FullParams) {
+        // SAFETY: the filter only reads `self` and writes the logits it is given
+        unsafe {
+            params.set_filter_logits_callback(Some(filter_logits));
+            params.set_filter_logits_callback_user_data(self as *const Self as *mut c_void);
+        }
+    }
+}
+
+/// Logits filter of whisper.cpp, generic over the context and state it does not use
+unsafe extern "C" fn filter_logits<C, S>(
+    _context: *mut C,
+    _state: *mut S,
+    tokens: *const WhisperTokenData,
+    n_tokens: c_int,
+    logits: *mut f32,
+    user_data: *mut c_void,
+) {
+    // SAFETY: `user_data` is set by `SuppressedTokens::install`, whisper.cpp passes the
+    // tokens decoded so far and the logits of the whole vocabulary
+    let (suppressed, tokens, logits) = unsafe {
+        let suppressed = &*(user_data as *const SuppressedTokens);
+        let tokens = match n_tokens {
+            0 => &[],
+            _ => slice::from_raw_parts(tokens, n_tokens as usize),
+        };
+        let logits = slice::from_raw_parts_mut(logits, suppressed.n_vocab);
+        (suppressed, tokens, logits)
+    };
+    let generated: Vec<_> = tokens.iter().map(|token| token.id).collect();
+    for token in suppressed.banned(&generated) {
+        logits[token as usize] = f32::NEG_INFINITY;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banned_completions() {
+        let suppressed = SuppressedTokens {
+            sequences: vec![vec![7], vec![1, 2, 3]],
+            n_vocab: 10,
+        };
+        let banned =
+            |generated: &[WhisperTokenId]| suppressed.banned(generated).collect::<Vec<_>>();
+        assert_eq!(banned(&[]), [7]);
+        assert_eq!(banned(&[1]), [7]);
+        assert_eq!(banned(&[5, 1, 2]), [7, 3]);
+        assert_eq!(banned(&[1, 2, 4]), [7]);
+    }
+
+    #[test]
+    fn filter_logits_callback() {
+        let suppressed = SuppressedTokens {
+            sequences: vec![vec![0], vec![1, 2]],
+            n_vocab: 3,
+        };
+        let tokens = [WhisperTokenData {
+            id: 1,
+            tid: 0,
+            p: 1.,
+            plog: 0.,
+            pt: 0.,
+            ptsum: 0.,
+            t0: 0,
+            t1: 0,
+            t_dtw: 0,
+            vlen: 0.,
+        }];
+        let mut logits = [1.; 3];
+        unsafe {
+            filter_logits::<(), ()>(
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                tokens.as_ptr(),
+                tokens.len() as c_int,
+                logits.as_mut_ptr(),
+                &suppressed as *const SuppressedTokens as *mut c_void,
+            )
+        };
+        assert_eq!(logits, [f32::NEG_INFINITY, 1., f32::NEG_INFINITY]);
+    }
+}
@@ -1,16 +1,28 @@
 use std::{
+    fs::metadata,
+    io,
     path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 
 use derive_builder::Builder;
-use tokio::sync::mpsc::UnboundedSender;
 use whisper_rs::{
     FullParams, SamplingStrategy, SegmentCallbackData, WhisperContext, WhisperContextParameters,
-    WhisperError, WhisperState,
+    WhisperError, WhisperState, WhisperTokenId,
 };
 
-use crate::{Error, Event, Language};
+use crate::{
+    Error, Event, Language, Suppress,
+    align::{TimedWord, WordDecoder},
+    header::ModelHeader,
+    recommend::available_memory,
+    suppress::SuppressedTokens,
+};
+
+/// Receiver of the events of a transcription, whatever drives it: a channel of an async
+/// runtime or of a thread
+pub(crate) type EventSink = Arc<dyn Fn(Result<Event, Error>) + Send + Sync>;
 
 #[derive(Builder)]
 #[builder(
@@ -21,12 +33,23 @@ use crate::{Error, Event, Language};
 pub struct Transcribe {
     language: Language,
     audio: (Vec<f32>, Duration),
-    tx: UnboundedSender<Result<Event, Error>>,
+    tx: EventSink,
     #[builder(setter(name = "model"))]
     _model: PathBuf,
+    /// Model already loaded by a [crate::Transcriber], the file is not loaded again
+    #[builder(setter(strip_option))]
+    _context: Option<Arc<WhisperContext>>,
     #[builder(setter(skip))]
     state: WhisperState,
     single_segment: bool,
+    /// Suppress the blank outputs at the beginning of the sampling, on by default
+    suppress_blank: bool,
+    /// Suppress the non-speech tokens: symbols, music notes and bracketed annotations
+    suppress_non_speech: bool,
+    #[builder(setter(name = "suppress"))]
+    _suppress: Vec<Suppress>,
+    #[builder(setter(skip))]
+    suppressed: SuppressedTokens,
 }
 
 impl TranscribeBuilder {
@@ -47,15 +70,47 @@ impl TranscribeBuilder {
             return Err(TranscribeBuilderError::UninitializedFieldError("model"));
         }
 
-        let state = state_builder(self._model.as_ref().unwrap())?;
+        let language = self.language.unwrap();
+        let model = self._model.unwrap();
+        let context = self._context.flatten();
+
+        let suppress = self._suppress.unwrap_or_default();
+        let (state, suppressed) = match &context {
+            Some(context) => {
+                if !language.is_english() && !context.is_multilingual() {
+                    return Err(TranscribeBuilderError::UnsupportedLanguage(language));
+                }
+                (
+                    context.create_state()?,
+                    SuppressedTokens::resolve(context, &suppress)?,
+                )
+            }
+            None => {
+                // Fail early on files whisper.cpp would not be able to load
+                let header = ModelHeader::read(&model)?;
+                if !language.is_english() && !header.is_multilingual() {
+                    return Err(TranscribeBuilderError::UnsupportedLanguage(language));
+                }
+                let context = context_builder(&model)?;
+                (
+                    context.create_state()?,
+                    SuppressedTokens::resolve(&context, &suppress)?,
+                )
+            }
+        };
 
         Ok(Transcribe {
-            language: self.language.unwrap(),
+            language,
             audio: self.audio.unwrap(),
             tx: self.tx.unwrap(),
-            _model: self._model.unwrap(),
+            _model: model,
+            _context: context,
             state,
             single_segment: self.single_segment.unwrap_or(false),
+            suppress_blank: self.suppress_blank.unwrap_or(true),
+            suppress_non_speech: self.suppress_non_speech.unwrap_or(false),
+            _suppress: suppress,
+            suppressed,
         })
     }
 }
@@ -67,9 +122,34 @@ pub enum TranscribeBuilderError {
     UninitializedFieldError(&'static str),
     #[error(transparent)]
     WhisperCppError(#[from] WhisperError),
+    #[error("The requested language {0} is not supported by the model")]
+    UnsupportedLanguage(Language),
+    #[error("Invalid model file: {0}")]
+    InvalidModel(#[from] io::Error),
+    #[error("The token {0} is not part of the model vocabulary")]
+    UnknownToken(WhisperTokenId),
+}
+
+/// Refuse to load a model the host does not have the memory to run.
+///
+/// The check is skipped where the available memory can not be detected.
+pub(crate) fn check_memory(model: &Path) -> Result<(), Error> {
+    let required = metadata(model)?.len() + ModelHeader::read(model)?.state_memory(threads());
+    match available_memory() {
+        Some(available) if required > available => Err(Error::InsufficientMemory {
+            required,
+            available,
+        }),
+        _ => Ok(()),
+    }
 }
 
-fn state_builder(model: &Path) -> Result<WhisperState, WhisperError> {
+/// Threads used by the transcription
+fn threads() -> usize {
+    num_cpus::get()
+}
+
+pub(crate) fn context_builder(model: &Path) -> Result<WhisperContext, WhisperError> {
     #![allow(unused_mut)]
     let mut context_param = WhisperContextParameters::default();
     #[cfg(any(
@@ -82,27 +162,18 @@ fn state_builder(model: &Path) -> Result<WhisperState, WhisperError> {
         context_param.use_gpu(true);
     }
 
-    let ctx = WhisperContext::new_with_params(model.to_str().unwrap(), context_param)?;
-
-    ctx.create_state()
+    WhisperContext::new_with_params(model.to_str().unwrap(), context_param)
 }
 
 impl Transcribe {
     pub fn transcribe(mut self) {
-        let tx_callback = self.tx.downgrade();
+        let tx_callback = Arc::downgrade(&self.tx);
 
         let (audio, duration) = &self.audio;
         let duration = *duration;
         let lang = self.language.to_string();
 
-        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 0 });
-        params.set_single_segment(self.single_segment);
-        params.set_n_threads(num_cpus::get().try_into().unwrap());
-        params.set_language(Some(&lang));
-        params.set_print_special(false);
-        params.set_print_progress(false);
-        params.set_print_timestamps(false);
-
+        let mut params = self.params(&lang);
         params.set_segment_callback_safe(move |seg: SegmentCallbackData| {
             let start_offset = Duration::from_millis(seg.start_timestamp as u64 * 10);
             let end_offset = Duration::from_millis(seg.end_timestamp as u64 * 10);
@@ -116,11 +187,58 @@ impl Transcribe {
                 percentage,
                 transcription: seg.text,
             };
-            let _ = tx_callback.upgrade().unwrap().send(Ok(seg));
+            if let Some(tx) = tx_callback.upgrade() {
+                tx(Ok(seg));
+            }
         });
 
         if let Err(err) = self.state.full(params, audio) {
-            let _ = self.tx.send(Err(Error::Whisper(err)));
+            (self.tx)(Err(Error::Whisper(err)));
+        }
+    }
+
+    /// Run the inference collecting the recognized words with their timing
+    pub fn words(mut self) -> Result<Vec<TimedWord>, Error> {
+        let lang = self.language.to_string();
+
+        let mut params = self.params(&lang);
+        params.set_token_timestamps(true);
+
+        let (audio, _) = &self.audio;
+        self.state.full(params, audio)?;
+
+        let mut words = WordDecoder::default();
+        for segment in self.state.as_iter() {
+            words.segment();
+            for idx in 0..segment.n_tokens() {
+                let Some(token) = segment.get_token(idx) else {
+                    continue;
+                };
+                let data = token.token_data();
+                words.push(
+                    token.to_bytes()?,
+                    Duration::from_millis(data.t0.max(0) as u64 * 10),
+                    Duration::from_millis(data.t1.max(0) as u64 * 10),
+                );
+            }
+        }
+        Ok(words.finish())
+    }
+
+    fn params<'a>(&self, lang: &'a str) -> FullParams<'a, 'a> {
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 0 });
+        params.set_single_segment(self.single_segment);
+        params.set_n_threads(threads().try_into().unwrap());
+        params.set_language(Some(lang));
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_timestamps(false);
+        params.set_suppress_blank(self.suppress_blank);
+        params.set_suppress_nst(self.suppress_non_speech);
+        if !self.suppressed.is_empty() {
+            // `self` outlives the inference
+            self.suppressed.install(&mut params);
         }
+        params
     }
 }
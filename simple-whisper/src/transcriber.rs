@@ -0,0 +1,279 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use derive_builder::Builder;
+use tokio::{
+    spawn,
+    sync::{
+        Mutex,
+        mpsc::{Sender, channel},
+    },
+    task::spawn_blocking,
+};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+use whisper_rs::WhisperContext;
+
+use crate::{
+    Backpressure, DEFAULT_CHANNEL_CAPACITY, DownloadConfig, DownloadObserver, Error, Event,
+    Language, ModelSource, Suppress, Whisper,
+    backpressure::DownloadEvents,
+    download::offline_from_env,
+    transcribe::{TranscribeBuilder, check_memory, context_builder},
+};
+
+/// Settings used by a [Transcriber] to fetch and load its models
+#[derive(Builder, Clone, Debug)]
+#[builder(setter(into), default, build_fn(validate = "Self::validate"))]
+pub struct TranscriberConfig {
+    /// Hub endpoint, cache directory, token, revision and proxy used to fetch the models
+    download_config: DownloadConfig,
+    /// Ignore the cached model files
+    force_download: bool,
+    /// Only use cached or local model files, never touching the network.
+    /// Defaults to the value of the `HF_HUB_OFFLINE` environment variable.
+    offline: bool,
+    /// Load the models even if the host does not seem to have the memory to run them
+    ignore_memory_check: bool,
+    force_single_segment: bool,
+    /// Suppress the blank outputs at the beginning of the sampling
+    suppress_blank: bool,
+    /// Suppress the non-speech tokens: symbols, music notes and bracketed annotations
+    suppress_non_speech: bool,
+    /// Texts or tokens the models must not output
+    suppress: Vec<Suppress>,
+    /// Events buffered in each stream for a consumer falling behind
+    channel_capacity: usize,
+    /// What happens once `channel_capacity` events are waiting
+    backpressure: Backpressure,
+    #[builder(setter(custom))]
+    download_observer: Option<Arc<dyn DownloadObserver>>,
+}
+
+impl Default for TranscriberConfig {
+    fn default() -> Self {
+        Self {
+            download_config: Default::default(),
+            force_download: false,
+            offline: offline_from_env(),
+            ignore_memory_check: false,
+            force_single_segment: false,
+            suppress_blank: true,
+            suppress_non_speech: false,
+            suppress: Vec::new(),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            backpressure: Backpressure::default(),
+            download_observer: None,
+        }
+    }
+}
+
+impl TranscriberConfigBuilder {
+    /// Observer notified of the model downloads, along with the events of the streams
+    pub fn download_observer(&mut self, observer: Arc<dyn DownloadObserver>) -> &mut Self {
+        self.download_observer = Some(Some(observer));
+        self
+    }
+
+    fn validate(&self) -> Result<(), TranscriberConfigBuilderError> {
+        if self.channel_capacity == Some(0) {
+            return Err(TranscriberConfigBuilderError::ValidationError(
+                "The event channel capacity must be greater than 0".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A model loaded in memory
+struct LoadedModel {
+    source: ModelSource,
+    path: PathBuf,
+    context: Arc<WhisperContext>,
+}
+
+/// A long-lived model serving many transcriptions.
+///
+/// The model is loaded once and shared by the jobs. [Transcriber::swap] loads another
+/// model in the background and replaces the current one for the new jobs, while the
+/// jobs in flight finish on the model they started with.
+#[derive(Clone)]
+pub struct Transcriber {
+    config: TranscriberConfig,
+    loaded: Arc<RwLock<Arc<LoadedModel>>>,
+    /// Serialize the swaps, the last requested model wins
+    swapping: Arc<Mutex<()>>,
+}
+
+impl Transcriber {
+    /// Download and load `model`
+    pub async fn load(
+        model: impl Into<ModelSource>,
+        config: TranscriberConfig,
+    ) -> Result<Self, Error> {
+        let loaded = load_model(model.into(), &config, None).await?;
+        Ok(Self {
+            config,
+            loaded: Arc::new(RwLock::new(Arc::new(loaded))),
+            swapping: Default::default(),
+        })
+    }
+
+    /// The model serving the new jobs
+    pub fn model(&self) -> ModelSource {
+        self.current().source.clone()
+    }
+
+    fn current(&self) -> Arc<LoadedModel> {
+        self.loaded.read().unwrap().clone()
+    }
+
+    /// Load `model` in the background, then use it for the new jobs.
+    ///
+    /// The stream reports the download, [Event::ModelLoading] and [Event::ModelSwapped].
+    /// On failure the current model keeps serving and the error ends the stream.
+    pub fn swap(&self, model: impl Into<ModelSource>) -> impl Stream<Item = Result<Event, Error>> {
+        let (tx, rx) = channel(self.config.channel_capacity);
+        let model = model.into();
+        let transcriber = self.clone();
+
+        spawn(async move {
+            let _swapping = transcriber.swapping.lock().await;
+            let backpressure = transcriber.config.backpressure;
+            let loaded = match load_model(model, &transcriber.config, Some(&tx)).await {
+                Ok(loaded) => Arc::new(loaded),
+                Err(err) => {
+                    backpressure.send(&tx, Err(err)).await;
+                    return;
+                }
+            };
+            let model = loaded.source.to_string();
+            let previous = std::mem::replace(&mut *transcriber.loaded.write().unwrap(), loaded);
+            let swapped = Event::ModelSwapped {
+                previous: previous.source.to_string(),
+                model,
+            };
+            backpressure.send(&tx, Ok(swapped)).await;
+        });
+
+        ReceiverStream::new(rx)
+    }
+
+    /// Transcribe an audio file with the current model
+    pub fn transcribe(
+        &self,
+        path: impl AsRef<Path>,
+        language: Language,
+    ) -> impl Stream<Item = Result<Event, Error>> {
+        let (tx, rx) = channel(self.config.channel_capacity);
+        let path = path.as_ref().into();
+        let loaded = self.current();
+        let config = self.config.clone();
+        let sink = self.config.backpressure.blocking_sink(tx);
+
+        spawn_blocking(move || {
+            let transcribe = Whisper::load_audio(path).and_then(|audio| {
+                TranscribeBuilder::default()
+                    .language(language)
+                    .audio(audio)
+                    .single_segment(config.force_single_segment)
+                    .suppress_blank(config.suppress_blank)
+                    .suppress_non_speech(config.suppress_non_speech)
+                    .suppress(config.suppress)
+                    .tx(sink.clone())
+                    .model(loaded.path.clone())
+                    ._context(loaded.context.clone())
+                    .build()
+                    .map_err(Error::from)
+            });
+            match transcribe {
+                Ok(transcribe) => transcribe.transcribe(),
+                Err(err) => sink(Err(err)),
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+}
+
+/// Fetch the model file and load it, reporting the progress on `tx`
+async fn load_model(
+    source: ModelSource,
+    config: &TranscriberConfig,
+    tx: Option<&Sender<Result<Event, Error>>>,
+) -> Result<LoadedModel, Error> {
+    let (events, rx_event) = DownloadEvents::observer();
+    let backpressure = config.backpressure;
+    let forwarder = tx
+        .cloned()
+        .map(|tx| spawn(async move { backpressure.forward(rx_event, &tx).await }));
+    let mut observers: Vec<Arc<dyn DownloadObserver>> = vec![Arc::new(events)];
+    observers.extend(config.download_observer.clone());
+    let path = source
+        .internal_download_model(
+            config.force_download,
+            config.offline,
+            Arc::new(observers),
+            &config.download_config,
+        )
+        .await;
+    // The download events precede the loading ones
+    if let Some(forwarder) = forwarder {
+        let _ = forwarder.await;
+    }
+    let path = path?;
+
+    if let Some(tx) = tx {
+        let loading = Event::ModelLoading {
+            model: source.to_string(),
+        };
+        backpressure.send(tx, Ok(loading)).await;
+    }
+    let ignore_memory_check = config.ignore_memory_check;
+    let model = path.clone();
+    let context = spawn_blocking(move || {
+        if !ignore_memory_check {
+            check_memory(&model)?;
+        }
+        Ok::<_, Error>(context_builder(&model)?)
+    })
+    .await
+    .map_err(std::io::Error::from)??;
+
+    Ok(LoadedModel {
+        source,
+        path,
+        context: Arc::new(context),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::Model;
+
+    #[ignore]
+    #[tokio::test]
+    async fn swap_model() {
+        let transcriber = Transcriber::load(Model::Tiny, TranscriberConfig::default())
+            .await
+            .unwrap();
+        let jfk = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/samples_jfk.wav");
+        let in_flight = transcriber.transcribe(jfk, Language::English);
+
+        let events: Vec<_> = transcriber.swap(Model::TinyEn).collect().await;
+        assert!(matches!(
+            events.last(),
+            Some(Ok(Event::ModelSwapped { previous, model }))
+                if *previous == Model::Tiny.to_string() && *model == Model::TinyEn.to_string()
+        ));
+        assert_eq!(transcriber.model(), ModelSource::Model(Model::TinyEn));
+
+        let segments: Vec<_> = in_flight.collect().await;
+        assert!(!segments.is_empty());
+        assert!(segments.iter().all(Result::is_ok));
+    }
+}